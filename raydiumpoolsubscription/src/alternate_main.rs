@@ -1,51 +1,256 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use futures::StreamExt;
+use solana_account_decoder::{UiAccountEncoding, UiDataSliceConfig};
 use solana_client::{
+    client_error::{ClientError, ClientErrorKind},
+    nonblocking::pubsub_client::PubsubClient,
     rpc_client::RpcClient,
-    rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
-    rpc_filter::{Memcmp, MemcmpEncodedBytes, RpcFilterType},
+    rpc_config::{
+        RpcAccountInfoConfig, RpcProgramAccountsConfig, RpcTransactionConfig,
+        RpcTransactionLogsConfig, RpcTransactionLogsFilter,
+    },
+    rpc_custom_error::{
+        JSON_RPC_SCAN_ERROR, JSON_RPC_SERVER_ERROR_KEY_EXCLUDED_FROM_SECONDARY_INDEX,
+    },
+    rpc_filter::RpcFilterType,
+    rpc_request::RpcError,
 };
-use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey};
-use std::str::FromStr;
+use solana_sdk::{bs58, commitment_config::CommitmentConfig, pubkey::Pubkey, signature::Signature};
+use solana_transaction_status::{
+    option_serializer::OptionSerializer, EncodedConfirmedTransactionWithStatusMeta,
+    EncodedTransaction, UiMessage, UiTransactionEncoding,
+};
+use std::{collections::BTreeSet, env, str::FromStr};
 use tokio::time::{sleep, Duration};
 
+/// Default cap on pools kept per snapshot; mainnet has far more than this.
+const DEFAULT_MAX_POOLS: usize = 50_000;
+/// Size of a Raydium AMM v4 pool account.
+const POOL_ACCOUNT_SIZE: u64 = 592;
+/// Most filters an RPC node accepts in one `getProgramAccounts` call.
+const MAX_PROGRAM_ACCOUNTS_FILTERS: usize = 4;
+/// Account AMM v4 `initialize2` pays its pool-creation fee into.
+const RAY_FEE: &str = "7YttLkHDoNj9wyDur5pM1ejNaAvT9X4eqaYcHQqtj2G5";
+/// Instruction tag of AMM v4 `initialize2`.
+const INITIALIZE2_TAG: u8 = 1;
+/// Position of the new pool (AMM id) among `initialize2`'s accounts.
+const INITIALIZE2_POOL_INDEX: usize = 4;
+
+/// Extra `getProgramAccounts` settings for callers who know what they need.
+/// The pool-size filter is always sent; `extra_filters` are added to it.
+#[derive(Debug, Clone, Default)]
+pub struct ProgramAccountsOptions {
+    pub extra_filters: Vec<RpcFilterType>,
+    /// Defaults to no account bytes, since only pubkeys are used.
+    pub data_slice: Option<UiDataSliceConfig>,
+    pub min_context_slot: Option<u64>,
+    pub with_context: Option<bool>,
+}
+
+impl ProgramAccountsOptions {
+    /// Rejects filters that are malformed, that can never match a pool account,
+    /// or that take the request past what an RPC node accepts.
+    pub fn validate(&self) -> Result<()> {
+        if self.extra_filters.len() + 1 > MAX_PROGRAM_ACCOUNTS_FILTERS {
+            return Err(anyhow!(
+                "At most {} extra filters fit alongside the pool-size filter",
+                MAX_PROGRAM_ACCOUNTS_FILTERS - 1
+            ));
+        }
+
+        for filter in &self.extra_filters {
+            filter
+                .verify()
+                .map_err(|err| anyhow!("Invalid filter {:?}: {}", filter, err))?;
+            if let RpcFilterType::DataSize(size) = filter {
+                if *size != POOL_ACCOUNT_SIZE {
+                    return Err(anyhow!(
+                        "DataSize({}) can't match pool accounts, which are {} bytes",
+                        size,
+                        POOL_ACCOUNT_SIZE
+                    ));
+                }
+            }
+        }
+
+        if let Some(slice) = &self.data_slice {
+            let end = (slice.offset as u64).checked_add(slice.length as u64);
+            if end.is_none_or(|end| end > POOL_ACCOUNT_SIZE) {
+                return Err(anyhow!(
+                    "Data slice {}..{} runs past the {}-byte pool account",
+                    slice.offset,
+                    slice.offset.saturating_add(slice.length),
+                    POOL_ACCOUNT_SIZE
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Pools from one `getProgramAccounts` snapshot, capped to the `max_pools`
+/// lowest pubkeys. The RPC returns accounts in no particular order, so the cap
+/// goes by key to keep the same pools from one snapshot to the next.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct PoolSnapshot {
+    pub pools: BTreeSet<Pubkey>,
+    /// Pools above the highest kept key were dropped by the cap.
+    pub truncated: bool,
+}
+
+impl PoolSnapshot {
+    fn capped(pubkeys: impl IntoIterator<Item = Pubkey>, max_pools: usize) -> Self {
+        let mut pools: BTreeSet<Pubkey> = pubkeys.into_iter().collect();
+        let total = pools.len();
+        let truncated = total > max_pools;
+        if truncated {
+            println!(
+                "Warning: got {} pools, keeping only the {} lowest keys",
+                total, max_pools
+            );
+            while pools.len() > max_pools {
+                pools.pop_last();
+            }
+        }
+        Self { pools, truncated }
+    }
+
+    /// Whether `pool` would have been kept in this snapshot if it existed. Keys
+    /// past the cap weren't looked at, so they can't be called new later.
+    fn covers(&self, pool: &Pubkey) -> bool {
+        !self.truncated || self.pools.last().is_some_and(|last| pool <= last)
+    }
+
+    /// Pools in `current` that `self` covered but didn't contain.
+    fn new_pools<'a>(&'a self, current: &'a PoolSnapshot) -> impl Iterator<Item = &'a Pubkey> {
+        current
+            .pools
+            .iter()
+            .filter(move |pool| self.covers(pool) && !self.pools.contains(pool))
+    }
+}
+
+/// How raw account bytes are rendered when printed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RawEncoding {
+    Base58,
+    #[default]
+    Base64,
+    Hex,
+}
+
+impl RawEncoding {
+    pub fn encode(self, bytes: &[u8]) -> String {
+        match self {
+            RawEncoding::Base58 => bs58::encode(bytes).into_string(),
+            RawEncoding::Base64 => STANDARD.encode(bytes),
+            RawEncoding::Hex => bytes.iter().map(|byte| format!("{:02x}", byte)).collect(),
+        }
+    }
+}
+
+impl FromStr for RawEncoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "base58" => Ok(RawEncoding::Base58),
+            "base64" => Ok(RawEncoding::Base64),
+            "hex" => Ok(RawEncoding::Hex),
+            other => Err(anyhow!(
+                "Unknown raw encoding '{}', expected base58, base64 or hex",
+                other
+            )),
+        }
+    }
+}
+
 pub struct RaydiumPoolListener {
     rpc_client: RpcClient,
+    /// Websocket endpoint used when snapshots are refused as too large.
+    ws_url: String,
     amm_program_id: Pubkey,
+    max_pools: usize,
+    raw_encoding: RawEncoding,
+    program_accounts_options: ProgramAccountsOptions,
 }
 
 impl RaydiumPoolListener {
     pub fn new(rpc_url: &str) -> Self {
-        let rpc_client =
-            RpcClient::new_with_commitment(rpc_url.to_string(), CommitmentConfig::confirmed());
+        Self::with_rpc_client(RpcClient::new_with_commitment(
+            rpc_url.to_string(),
+            CommitmentConfig::confirmed(),
+        ))
+    }
 
+    pub fn with_rpc_client(rpc_client: RpcClient) -> Self {
         // Raydium AMM Program ID
         let amm_program_id = Pubkey::from_str("675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8")
             .expect("Failed to parse Raydium AMM program ID");
 
+        // The websocket is served next to the HTTP endpoint: https -> wss.
+        let ws_url = rpc_client.url().replacen("http", "ws", 1);
+
         Self {
             rpc_client,
+            ws_url,
             amm_program_id,
+            max_pools: DEFAULT_MAX_POOLS,
+            raw_encoding: RawEncoding::default(),
+            program_accounts_options: ProgramAccountsOptions::default(),
         }
     }
 
+    /// Replaces the extra `getProgramAccounts` settings, after validating them.
+    pub fn set_program_accounts_options(&mut self, options: ProgramAccountsOptions) -> Result<()> {
+        options.validate()?;
+        self.program_accounts_options = options;
+        Ok(())
+    }
+
+    /// Sets how raw pool account data is printed.
+    pub fn set_raw_encoding(&mut self, raw_encoding: RawEncoding) {
+        self.raw_encoding = raw_encoding;
+    }
+
+    /// Caps how many pools a snapshot keeps and diffs, by lowest pubkey; the
+    /// rest are dropped with a warning and never reported as new. The full
+    /// `getProgramAccounts` response is still read; narrow it with
+    /// `ProgramAccountsOptions::extra_filters` to bound that.
+    pub fn set_max_pools(&mut self, max_pools: usize) {
+        self.max_pools = max_pools;
+    }
+
     pub async fn start_listening(&self) -> Result<()> {
         println!("Starting to listen for new Raydium pool creation...");
 
         // Keep track of pools we've already seen
-        let mut known_pools = self.get_existing_pools()?;
-        println!("Found {} existing pools", known_pools.len());
+        let mut known_pools = match self.get_existing_pools() {
+            Ok(pools) => pools,
+            Err(err) if is_response_too_large(&err) => {
+                return self.follow_pool_creations(err).await
+            }
+            Err(err) => return Err(err),
+        };
+        println!("Found {} existing pools", known_pools.pools.len());
 
         loop {
             // Get current pools
-            let current_pools = self.get_existing_pools()?;
+            let current_pools = match self.get_existing_pools() {
+                Ok(pools) => pools,
+                Err(err) if is_response_too_large(&err) => {
+                    return self.follow_pool_creations(err).await
+                }
+                Err(err) => return Err(err),
+            };
 
             // Find new pools
-            for pool in current_pools.iter() {
-                if !known_pools.contains(pool) {
-                    println!("New pool detected: {}", pool);
-                    // Here you can add custom logic to handle new pools
-                    self.process_new_pool(pool)?;
-                }
+            for pool in known_pools.new_pools(&current_pools) {
+                println!("New pool detected: {}", pool);
+                // Here you can add custom logic to handle new pools
+                self.process_new_pool(pool)?;
             }
 
             // Update known pools
@@ -56,25 +261,95 @@ impl RaydiumPoolListener {
         }
     }
 
-    fn get_existing_pools(&self) -> Result<Vec<Pubkey>> {
-        let config = RpcProgramAccountsConfig {
-            filters: Some(vec![
-                RpcFilterType::DataSize(592), // Raydium pool account size
-            ]),
+    /// One-shot snapshot of the pools that exist right now, without the polling loop.
+    pub fn list_pools(&self) -> Result<Vec<Pubkey>> {
+        let pools: Vec<Pubkey> = self.get_existing_pools()?.pools.into_iter().collect();
+
+        for pool in pools.iter() {
+            println!("{}", pool);
+        }
+        println!("Listed {} pools", pools.len());
+
+        Ok(pools)
+    }
+
+    /// Asks for pool accounts by size plus the configured extra options.
+    fn program_accounts_config(&self) -> RpcProgramAccountsConfig {
+        let options = &self.program_accounts_options;
+
+        let mut filters = vec![RpcFilterType::DataSize(POOL_ACCOUNT_SIZE)];
+        filters.extend(options.extra_filters.iter().cloned());
+
+        RpcProgramAccountsConfig {
+            filters: Some(filters),
             account_config: RpcAccountInfoConfig {
-                encoding: None,
-                data_slice: None,
+                // Only the pubkeys are used here, `process_new_pool` fetches the
+                // data it needs, so by default ask for no account bytes at all.
+                encoding: Some(UiAccountEncoding::Base64),
+                data_slice: Some(options.data_slice.unwrap_or(UiDataSliceConfig {
+                    offset: 0,
+                    length: 0,
+                })),
                 commitment: Some(CommitmentConfig::confirmed()),
-                min_context_slot: None,
+                min_context_slot: options.min_context_slot,
             },
-            with_context: None,
-        };
+            with_context: options.with_context,
+        }
+    }
+
+    fn get_existing_pools(&self) -> Result<PoolSnapshot> {
+        let config = self.program_accounts_config();
 
         let accounts = self
             .rpc_client
             .get_program_accounts_with_config(&self.amm_program_id, config)?;
 
-        Ok(accounts.into_iter().map(|(pubkey, _)| pubkey).collect())
+        Ok(PoolSnapshot::capped(
+            accounts.into_iter().map(|(pubkey, _)| pubkey),
+            self.max_pools,
+        ))
+    }
+
+    /// Listens for pool creations over `logsSubscribe` on the fee account
+    /// instead of diffing snapshots, for providers that refuse the full
+    /// `getProgramAccounts` response.
+    async fn follow_pool_creations(&self, refused: anyhow::Error) -> Result<()> {
+        println!(
+            "Provider refused getProgramAccounts as too large ({}); \
+             following pool-creation logs instead",
+            refused
+        );
+
+        let pubsub_client = PubsubClient::new(&self.ws_url).await?;
+        let (mut notifications, _unsubscribe) = pubsub_client
+            .logs_subscribe(
+                RpcTransactionLogsFilter::Mentions(vec![RAY_FEE.to_string()]),
+                RpcTransactionLogsConfig {
+                    commitment: Some(CommitmentConfig::confirmed()),
+                },
+            )
+            .await?;
+
+        while let Some(response) = notifications.next().await {
+            let logs = response.value;
+            if logs.err.is_some() {
+                continue;
+            }
+            let transaction = self.rpc_client.get_transaction_with_config(
+                &Signature::from_str(&logs.signature)?,
+                RpcTransactionConfig {
+                    encoding: Some(UiTransactionEncoding::Json),
+                    commitment: Some(CommitmentConfig::confirmed()),
+                    max_supported_transaction_version: Some(0),
+                },
+            )?;
+            if let Some(pool) = initialized_pool(&transaction, &self.amm_program_id) {
+                println!("New pool detected: {}", pool);
+                self.process_new_pool(&pool)?;
+            }
+        }
+
+        Err(anyhow!("Pool-creation log subscription closed"))
     }
 
     fn process_new_pool(&self, pool_address: &Pubkey) -> Result<()> {
@@ -89,16 +364,370 @@ impl RaydiumPoolListener {
 
         println!("Processing new pool: {}", pool_address);
         println!("Data length: {} bytes", account.data.len());
+        println!("Data: {}", self.raw_encoding.encode(&account.data));
 
         Ok(())
     }
 }
 
+/// Whether a provider refused `getProgramAccounts` for the size of the
+/// result: an HTTP 413, a program excluded from the account index, or a scan
+/// aborted past the node's byte limit.
+fn is_response_too_large(err: &anyhow::Error) -> bool {
+    let Some(err) = err.downcast_ref::<ClientError>() else {
+        return false;
+    };
+    match err.kind() {
+        ClientErrorKind::Reqwest(err) => {
+            err.status() == Some(reqwest::StatusCode::PAYLOAD_TOO_LARGE)
+        }
+        ClientErrorKind::RpcError(RpcError::RpcResponseError { code, .. }) => matches!(
+            *code,
+            JSON_RPC_SERVER_ERROR_KEY_EXCLUDED_FROM_SECONDARY_INDEX | JSON_RPC_SCAN_ERROR
+        ),
+        _ => false,
+    }
+}
+
+/// The pool account an AMM v4 `initialize2` in `transaction` created, if any.
+fn initialized_pool(
+    transaction: &EncodedConfirmedTransactionWithStatusMeta,
+    amm_program_id: &Pubkey,
+) -> Option<Pubkey> {
+    let EncodedTransaction::Json(ui_transaction) = &transaction.transaction.transaction else {
+        return None;
+    };
+    let UiMessage::Raw(message) = &ui_transaction.message else {
+        return None;
+    };
+    // Keys loaded from lookup tables follow the static ones, writable first.
+    let mut account_keys = message.account_keys.clone();
+    if let Some(OptionSerializer::Some(loaded)) = transaction
+        .transaction
+        .meta
+        .as_ref()
+        .map(|meta| &meta.loaded_addresses)
+    {
+        account_keys.extend(loaded.writable.iter().cloned());
+        account_keys.extend(loaded.readonly.iter().cloned());
+    }
+
+    let program_id = amm_program_id.to_string();
+    message
+        .instructions
+        .iter()
+        .filter(|instruction| {
+            account_keys.get(instruction.program_id_index as usize) == Some(&program_id)
+        })
+        .find(|instruction| {
+            bs58::decode(&instruction.data)
+                .into_vec()
+                .is_ok_and(|data| data.first() == Some(&INITIALIZE2_TAG))
+        })
+        .and_then(|instruction| instruction.accounts.get(INITIALIZE2_POOL_INDEX))
+        .and_then(|index| account_keys.get(*index as usize))
+        .and_then(|key| Pubkey::from_str(key).ok())
+}
+
 // Usage example
 #[tokio::main]
 async fn main() -> Result<()> {
     let rpc_url = "https://raydium-raydium-5ad5.mainnet.rpcpool.com";
-    let listener = RaydiumPoolListener::new(rpc_url);
+    let mut listener = RaydiumPoolListener::new(rpc_url);
+
+    if let Some(encoding) =
+        env::args().find_map(|arg| arg.strip_prefix("--raw-encoding=").map(str::to_string))
+    {
+        listener.set_raw_encoding(encoding.parse()?);
+    }
+
+    // `--once` prints the current pools and exits instead of listening.
+    if env::args().any(|arg| arg == "--once") {
+        listener.list_pools()?;
+        return Ok(());
+    }
+
     listener.start_listening().await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_client::{
+        rpc_client::Mocks,
+        rpc_request::{RpcRequest, RpcResponseErrorData},
+    };
+    use std::{
+        io::{Read, Write},
+        net::{TcpListener, TcpStream},
+    };
+
+    /// A listener whose `getProgramAccounts` returns one empty account per key.
+    fn listener_over(pubkeys: &[Pubkey]) -> RaydiumPoolListener {
+        let accounts: Vec<_> = pubkeys
+            .iter()
+            .map(|pubkey| {
+                serde_json::json!({
+                    "pubkey": pubkey.to_string(),
+                    "account": {
+                        "lamports": 1,
+                        "data": ["", "base64"],
+                        "owner": "675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8",
+                        "executable": false,
+                        "rentEpoch": 0,
+                    },
+                })
+            })
+            .collect();
+        let mocks = Mocks::from([(RpcRequest::GetProgramAccounts, accounts.into())]);
+        RaydiumPoolListener::with_rpc_client(RpcClient::new_mock_with_mocks(
+            "succeeds".to_string(),
+            mocks,
+        ))
+    }
+
+    fn keys(count: u8) -> Vec<Pubkey> {
+        (1..=count)
+            .map(|n| Pubkey::new_from_array([n; 32]))
+            .collect()
+    }
+
+    #[test]
+    fn snapshot_keeps_the_lowest_keys_past_the_cap() {
+        let mut pubkeys = keys(5);
+        pubkeys.reverse();
+
+        let snapshot = PoolSnapshot::capped(pubkeys, 3);
+
+        assert!(snapshot.truncated);
+        assert_eq!(snapshot.pools.into_iter().collect::<Vec<_>>(), keys(3));
+    }
+
+    #[test]
+    fn snapshot_under_the_cap_is_not_truncated() {
+        let snapshot = PoolSnapshot::capped(keys(3), 3);
+
+        assert!(!snapshot.truncated);
+        assert_eq!(snapshot.pools.len(), 3);
+    }
+
+    #[test]
+    fn pools_past_a_truncated_snapshot_are_not_new() {
+        let all = keys(5);
+        let known = PoolSnapshot::capped(all[..4].to_vec(), 2);
+        // A pool closed, so the cap now reaches one key further.
+        let current = PoolSnapshot::capped(vec![all[0], all[2], all[3]], 2);
+
+        assert_eq!(known.new_pools(&current).count(), 0);
+    }
+
+    #[test]
+    fn new_pools_inside_the_covered_range_are_reported() {
+        let all = keys(5);
+        let known = PoolSnapshot::capped(vec![all[0], all[2], all[4]], 3);
+        let current = PoolSnapshot::capped(all.clone(), 3);
+
+        assert_eq!(known.new_pools(&current).collect::<Vec<_>>(), vec![&all[1]]);
+    }
+
+    #[test]
+    fn data_slice_past_the_account_is_rejected() {
+        let options = ProgramAccountsOptions {
+            data_slice: Some(UiDataSliceConfig {
+                offset: usize::MAX,
+                length: usize::MAX,
+            }),
+            ..Default::default()
+        };
+
+        let err = options.validate().unwrap_err().to_string();
+
+        assert!(err.contains("runs past the"), "{}", err);
+    }
+
+    #[test]
+    fn data_size_filter_must_match_pool_accounts() {
+        let options = ProgramAccountsOptions {
+            extra_filters: vec![RpcFilterType::DataSize(POOL_ACCOUNT_SIZE + 1)],
+            ..Default::default()
+        };
+
+        assert!(options.validate().is_err());
+        assert!(ProgramAccountsOptions::default().validate().is_ok());
+    }
+
+    #[test]
+    fn config_asks_only_for_the_bytes_needed() {
+        let listener = listener_over(&[]);
+
+        let slice = listener
+            .program_accounts_config()
+            .account_config
+            .data_slice
+            .unwrap();
+
+        assert_eq!((slice.offset, slice.length), (0, 0));
+    }
+
+    #[test]
+    fn oversized_responses_are_recognised() {
+        let rpc_error = |code| {
+            anyhow::Error::from(ClientError::from(ClientErrorKind::RpcError(
+                RpcError::RpcResponseError {
+                    code,
+                    message: "scan aborted".to_string(),
+                    data: RpcResponseErrorData::Empty,
+                },
+            )))
+        };
+        // Mentions "too large" and "413" without being either.
+        let other = anyhow::Error::from(ClientError::from(ClientErrorKind::Custom(
+            "connection reset after 413 ms, response too large?".to_string(),
+        )));
+
+        assert!(is_response_too_large(&rpc_error(
+            JSON_RPC_SERVER_ERROR_KEY_EXCLUDED_FROM_SECONDARY_INDEX
+        )));
+        assert!(is_response_too_large(&rpc_error(JSON_RPC_SCAN_ERROR)));
+        assert!(!is_response_too_large(&rpc_error(-32005)));
+        assert!(!is_response_too_large(&other));
+    }
+
+    /// Reads one request, headers and body, off `stream`.
+    fn read_http_request(stream: &mut TcpStream) -> String {
+        let mut request = Vec::new();
+        let mut buf = [0; 4096];
+        loop {
+            let read = stream.read(&mut buf).unwrap();
+            request.extend_from_slice(&buf[..read]);
+            let text = String::from_utf8_lossy(&request);
+            if let Some((headers, body)) = text.split_once("\r\n\r\n") {
+                let content_length = headers
+                    .lines()
+                    .find_map(|line| {
+                        line.to_lowercase()
+                            .strip_prefix("content-length: ")
+                            .map(str::to_string)
+                    })
+                    .and_then(|len| len.trim().parse().ok())
+                    .unwrap_or(0);
+                if read == 0 || body.len() >= content_length {
+                    return text.into_owned();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn http_413_is_a_response_too_large() {
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", server.local_addr().unwrap());
+        // Answers the version check `getProgramAccounts` makes first, then
+        // refuses the scan itself.
+        std::thread::spawn(move || {
+            for stream in server.incoming() {
+                let mut stream = stream.unwrap();
+                let response = if read_http_request(&mut stream).contains("getVersion") {
+                    let body = r#"{"jsonrpc":"2.0","result":{"solana-core":"1.18.26"},"id":1}"#;
+                    format!(
+                        "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\n\
+                         content-length: {}\r\nconnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    )
+                } else {
+                    "HTTP/1.1 413 Payload Too Large\r\ncontent-length: 0\r\n\
+                     connection: close\r\n\r\n"
+                        .to_string()
+                };
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        let listener = RaydiumPoolListener::new(&url);
+
+        let err = listener.get_existing_pools().unwrap_err();
+
+        assert!(is_response_too_large(&err), "{}", err);
+        assert_eq!(listener.ws_url, url.replacen("http", "ws", 1));
+    }
+
+    #[test]
+    fn the_pool_comes_from_the_initialize2_accounts() {
+        let keys: Vec<String> = (0..6).map(|_| Pubkey::new_unique().to_string()).collect();
+        let amm_program_id = Pubkey::new_unique();
+        let mut account_keys = keys.clone();
+        account_keys.push(amm_program_id.to_string());
+        let instruction = |tag: u8| {
+            serde_json::json!({
+                "programIdIndex": 6,
+                "accounts": [0, 1, 2, 3, 5, 4],
+                "data": bs58::encode([tag, 254, 0]).into_string(),
+                "stackHeight": null,
+            })
+        };
+        let transaction = |instructions| {
+            serde_json::from_value(serde_json::json!({
+                "slot": 1,
+                "blockTime": null,
+                "transaction": {
+                    "signatures": [Signature::default().to_string()],
+                    "message": {
+                        "header": {
+                            "numRequiredSignatures": 1,
+                            "numReadonlySignedAccounts": 0,
+                            "numReadonlyUnsignedAccounts": 1,
+                        },
+                        "accountKeys": account_keys,
+                        "recentBlockhash": Pubkey::default().to_string(),
+                        "instructions": instructions,
+                    },
+                },
+                "meta": null,
+            }))
+            .unwrap()
+        };
+
+        // A swap (tag 9) names the same accounts but creates nothing.
+        let created = transaction(vec![instruction(9), instruction(INITIALIZE2_TAG)]);
+        let swap_only = transaction(vec![instruction(9)]);
+
+        assert_eq!(
+            initialized_pool(&created, &amm_program_id),
+            Some(Pubkey::from_str(&keys[5]).unwrap())
+        );
+        assert_eq!(initialized_pool(&swap_only, &amm_program_id), None);
+    }
+
+    #[test]
+    fn raw_bytes_render_in_the_chosen_encoding() {
+        let bytes = [0x00, 0x01, 0xfe, 0xff];
+        let encode = |name: &str| name.parse::<RawEncoding>().unwrap().encode(&bytes);
+
+        assert_eq!(encode("base58"), "1ftS");
+        assert_eq!(encode("base64"), "AAH+/w==");
+        assert_eq!(encode("hex"), "0001feff");
+        assert_eq!(RawEncoding::default(), RawEncoding::Base64);
+        assert!("base32".parse::<RawEncoding>().is_err());
+    }
+
+    #[test]
+    fn list_pools_returns_the_current_accounts() {
+        let pools = keys(2);
+        let listener = listener_over(&[pools[1], pools[0]]);
+
+        assert_eq!(listener.list_pools().unwrap(), pools);
+    }
+
+    #[test]
+    fn pools_past_the_cap_are_truncated() {
+        let pools = keys(3);
+        let mut listener = listener_over(&pools);
+        listener.set_max_pools(2);
+
+        let snapshot = listener.get_existing_pools().unwrap();
+
+        assert!(snapshot.truncated);
+        assert_eq!(snapshot.pools.into_iter().collect::<Vec<_>>(), pools[..2]);
+    }
+}
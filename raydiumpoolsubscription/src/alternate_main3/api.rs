@@ -0,0 +1,185 @@
+use {
+    super::{parse_timestamp, read_token_data, storage::open_records, SchemaMode, TokenData},
+    anyhow::Result,
+    axum::{
+        extract::{Query, State},
+        http::{header, StatusCode},
+        routing::get,
+        Json, Router,
+    },
+    chrono::{DateTime, Utc},
+    serde::{Deserialize, Serialize},
+    std::{
+        io::BufRead,
+        net::SocketAddr,
+        path::{Path, PathBuf},
+        sync::Arc,
+    },
+};
+
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1_000;
+/// Items in `/feed.xml`, newest first.
+const FEED_ITEMS: usize = 50;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct PoolsQuery {
+    pub quote_mint: Option<String>,
+    pub since: Option<String>,
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PoolsPage {
+    pub pools: Vec<TokenData>,
+    /// Pass as `offset` to get the next page; absent on the last page.
+    pub next_offset: Option<usize>,
+}
+
+/// Applies `query` to `records`, which are in storage (oldest first) order.
+pub fn query_pools(
+    records: impl IntoIterator<Item = TokenData>,
+    query: &PoolsQuery,
+) -> Result<PoolsPage> {
+    let since = query
+        .since
+        .as_deref()
+        .map(DateTime::parse_from_rfc3339)
+        .transpose()?
+        .map(|since| since.with_timezone(&Utc));
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+    let offset = query.offset.unwrap_or(0);
+
+    let mut matching = records
+        .into_iter()
+        .filter(|data| {
+            query
+                .quote_mint
+                .as_ref()
+                .is_none_or(|mint| *mint == data.quote_info.address)
+        })
+        .filter(|data| {
+            since.is_none_or(|since| {
+                parse_timestamp(&data.timestamp).is_some_and(|timestamp| timestamp >= since)
+            })
+        })
+        .skip(offset);
+
+    let pools: Vec<_> = matching.by_ref().take(limit).collect();
+    let next_offset = matching.next().map(|_| offset + pools.len());
+
+    Ok(PoolsPage { pools, next_offset })
+}
+
+fn load_pools(path: &Path) -> Result<Vec<TokenData>> {
+    let mut pools = Vec::new();
+    for line in open_records(path)?.lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            pools.push(read_token_data(&line, SchemaMode::Lenient)?);
+        }
+    }
+    Ok(pools)
+}
+
+/// `load_pools` off the async runtime, with errors as a 500 response.
+async fn load_pools_in_background(
+    data_path: Arc<PathBuf>,
+) -> Result<Vec<TokenData>, (StatusCode, String)> {
+    tokio::task::spawn_blocking(move || load_pools(&data_path))
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))
+}
+
+async fn list_pools(
+    State(data_path): State<Arc<PathBuf>>,
+    Query(query): Query<PoolsQuery>,
+) -> Result<Json<PoolsPage>, (StatusCode, String)> {
+    let pools = load_pools_in_background(data_path).await?;
+
+    query_pools(pools, &query)
+        .map(Json)
+        .map_err(|err| (StatusCode::BAD_REQUEST, err.to_string()))
+}
+
+/// RSS 2.0 for the newest `FEED_ITEMS` of `pools` (oldest first). Items link
+/// to the pool-creation transaction on Solscan and are dated by
+/// `block_time`, falling back to the detection `timestamp`.
+pub fn render_feed(pools: &[TokenData]) -> String {
+    let mut xml = String::from(concat!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+        "\n",
+        r#"<rss version="2.0"><channel>"#,
+        "<title>New Raydium pools</title>",
+        "<link>https://raydium.io/</link>",
+        "<description>Pools detected by the Raydium monitor</description>",
+    ));
+
+    for data in pools.iter().rev().take(FEED_ITEMS) {
+        let published = data
+            .block_time
+            .and_then(|block_time| DateTime::from_timestamp(block_time, 0))
+            .or_else(|| parse_timestamp(&data.timestamp));
+        let link = format!("https://solscan.io/tx/{}", data.lp_signature);
+
+        xml.push_str("<item>");
+        xml.push_str(&format!(
+            "<title>{}</title>",
+            escape_xml(&format!("New pool for {}", data.base_info.address))
+        ));
+        xml.push_str(&format!("<link>{}</link>", escape_xml(&link)));
+        xml.push_str(&format!(
+            r#"<guid isPermaLink="false">{}</guid>"#,
+            escape_xml(&data.lp_signature)
+        ));
+        xml.push_str(&format!(
+            "<description>{}</description>",
+            escape_xml(&format!(
+                "Base {} / quote {}, pool {}",
+                data.base_info.address, data.quote_info.address, data.pool_address
+            ))
+        ));
+        if let Some(published) = published {
+            xml.push_str(&format!("<pubDate>{}</pubDate>", published.to_rfc2822()));
+        }
+        xml.push_str("</item>");
+    }
+
+    xml.push_str("</channel></rss>\n");
+    xml
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+async fn feed(
+    State(data_path): State<Arc<PathBuf>>,
+) -> Result<([(header::HeaderName, &'static str); 1], String), (StatusCode, String)> {
+    let pools = load_pools_in_background(data_path).await?;
+
+    Ok((
+        [(header::CONTENT_TYPE, "application/rss+xml; charset=utf-8")],
+        render_feed(&pools),
+    ))
+}
+
+pub fn router(data_path: PathBuf) -> Router {
+    Router::new()
+        .route("/pools", get(list_pools))
+        .route("/feed.xml", get(feed))
+        .with_state(Arc::new(data_path))
+}
+
+pub async fn serve(addr: SocketAddr, data_path: PathBuf) -> Result<()> {
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    log::info!("Serving pools API on {}", addr);
+    axum::serve(listener, router(data_path)).await?;
+    Ok(())
+}
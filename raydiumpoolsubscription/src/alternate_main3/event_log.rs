@@ -0,0 +1,211 @@
+use {
+    super::DetectionEvent,
+    anyhow::Result,
+    log::warn,
+    serde::{Deserialize, Serialize},
+    std::path::{Path, PathBuf},
+    tokio::{
+        fs::{File, OpenOptions},
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    },
+};
+
+/// One line of the event log.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LoggedEvent {
+    pub offset: u64,
+    pub event: DetectionEvent,
+}
+
+/// Append-only log of every `DetectionEvent`, one `LoggedEvent` JSON line
+/// each. Offsets start at 1 and only grow, so a consumer can acknowledge
+/// everything up to an offset with an `EventCursor` and pick up from there
+/// after either side restarts.
+pub struct EventLog {
+    /// The open file and the offset the next event gets.
+    file: tokio::sync::Mutex<(tokio::fs::File, u64)>,
+}
+
+impl EventLog {
+    /// Opens the log at `path`, creating it if missing. The existing lines are
+    /// streamed through once to find the next offset, never held in memory.
+    pub async fn open(path: &Path) -> Result<Self> {
+        let mut next_offset = 1;
+        let torn = match scan_log(path, 0, |logged| {
+            next_offset = logged.offset + 1;
+            Ok(())
+        })
+        .await
+        {
+            Ok(torn) => torn,
+            Err(err) if is_not_found(&err) => false,
+            Err(err) => return Err(err),
+        };
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+        // Otherwise the next event would be appended to the torn line and lost with it.
+        if torn {
+            file.write_all(b"\n").await?;
+        }
+
+        Ok(Self {
+            file: tokio::sync::Mutex::new((file, next_offset)),
+        })
+    }
+
+    /// Appends `event` and syncs it to disk, returning its offset.
+    pub async fn append(&self, event: &DetectionEvent) -> Result<u64> {
+        let mut guard = self.file.lock().await;
+        let (file, next_offset) = &mut *guard;
+        let offset = *next_offset;
+
+        let mut line = serde_json::to_string(&serde_json::json!({
+            "offset": offset,
+            "event": event,
+        }))?;
+        line.push('\n');
+        file.write_all(line.as_bytes()).await?;
+        file.sync_data().await?;
+
+        *next_offset += 1;
+        Ok(offset)
+    }
+}
+
+/// Calls `each` with every event in the log at `path` with an offset above
+/// `after`, in order, reading one line at a time.
+pub async fn for_each_logged_event(
+    path: &Path,
+    after: u64,
+    each: impl FnMut(LoggedEvent) -> Result<()>,
+) -> Result<()> {
+    scan_log(path, after, each).await.map(|_torn| ())
+}
+
+/// Streams the log at `path` through `each` and returns whether it stops
+/// partway through a line, as a crash in the middle of a write leaves it.
+async fn scan_log(
+    path: &Path,
+    after: u64,
+    mut each: impl FnMut(LoggedEvent) -> Result<()>,
+) -> Result<bool> {
+    let mut reader = BufReader::new(File::open(path).await?);
+    let mut line = String::new();
+    let mut line_number = 0;
+
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).await? == 0 {
+            return Ok(false);
+        }
+        line_number += 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<LoggedEvent>(&line) {
+            Ok(logged) if logged.offset > after => each(logged)?,
+            Ok(_) => {}
+            // A crash mid-write can leave a torn last line; it was never acked.
+            Err(err) => warn!(
+                "{}:{}: skipping bad event: {}",
+                path.display(),
+                line_number,
+                err
+            ),
+        }
+        if !line.ends_with('\n') {
+            return Ok(true);
+        }
+    }
+}
+
+fn is_not_found(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<std::io::Error>()
+        .is_some_and(|err| err.kind() == std::io::ErrorKind::NotFound)
+}
+
+/// A consumer's position in an `EventLog`: the highest offset it has fully
+/// handled. Stored as a single number, replaced atomically on every ack.
+pub struct EventCursor {
+    path: PathBuf,
+}
+
+impl EventCursor {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// Last acknowledged offset, 0 if nothing has been acknowledged yet.
+    pub fn acked(&self) -> Result<u64> {
+        match std::fs::read_to_string(&self.path) {
+            Ok(contents) => Ok(contents.trim().parse()?),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(0),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn ack(&self, offset: u64) -> Result<()> {
+        let mut temp_name = self.path.as_os_str().to_owned();
+        temp_name.push(".tmp");
+        let temp_path = PathBuf::from(temp_name);
+
+        std::fs::write(&temp_path, offset.to_string())?;
+        std::fs::rename(&temp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn delivery_resumes_after_the_last_acked_offset() {
+        let dir = tempfile::tempdir().unwrap();
+        let (wal, cursor) = (dir.path().join("events.wal"), dir.path().join("cursor"));
+        let filtered = |n: u32| DetectionEvent::Filtered {
+            signature: format!("signature-{}", n),
+            reason: "test".to_string(),
+        };
+
+        let log = EventLog::open(&wal).await.unwrap();
+        for n in 1..=3 {
+            assert_eq!(log.append(&filtered(n)).await.unwrap(), n as u64);
+        }
+        let consumer = EventCursor::new(cursor.clone());
+        consumer.ack(2).unwrap();
+        // Crash mid-write, leaving a torn line behind.
+        drop(log);
+        let mut file = std::fs::OpenOptions::new().append(true).open(&wal).unwrap();
+        std::io::Write::write_all(&mut file, br#"{"offset":4,"ev"#).unwrap();
+        drop(file);
+
+        let log = EventLog::open(&wal).await.unwrap();
+        assert_eq!(log.append(&filtered(4)).await.unwrap(), 4);
+        let acked = EventCursor::new(cursor).acked().unwrap();
+        let mut pending = Vec::new();
+        for_each_logged_event(&wal, acked, |logged| {
+            match logged.event {
+                DetectionEvent::Filtered { signature, .. } => {
+                    pending.push((logged.offset, signature))
+                }
+                other => panic!("expected a filtered signature, got {:?}", other),
+            }
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(acked, 2);
+        assert_eq!(
+            pending,
+            [
+                (3, "signature-3".to_string()),
+                (4, "signature-4".to_string())
+            ]
+        );
+    }
+}
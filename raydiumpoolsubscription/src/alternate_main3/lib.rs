@@ -0,0 +1,6523 @@
+#[cfg(feature = "api")]
+pub mod api;
+pub mod config;
+pub mod event_log;
+pub mod storage;
+
+use {
+    anyhow::{anyhow, Result},
+    chrono::{DateTime, NaiveDate, Utc},
+    colored::*,
+    config::{AppConfig, DedupKey, MonitorConfig, SourceKind},
+    event_log::EventLog,
+    futures::StreamExt,
+    log::{error, info, warn},
+    lru::LruCache,
+    reqwest::header::HeaderValue,
+    rust_decimal::Decimal,
+    serde::{Deserialize, Serialize},
+    serde_json::Value,
+    solana_account_decoder::parse_token::UiTokenAmount,
+    solana_client::{
+        nonblocking::pubsub_client::PubsubClient,
+        nonblocking::rpc_client::RpcClient,
+        rpc_client::{GetConfirmedSignaturesForAddress2Config, RpcClientConfig},
+        rpc_config::{RpcTransactionConfig, RpcTransactionLogsConfig, RpcTransactionLogsFilter},
+        rpc_request::RpcRequest,
+        rpc_response::{
+            Response, RpcConfirmedTransactionStatusWithSignature, RpcTokenAccountBalance,
+            RpcVersionInfo,
+        },
+    },
+    solana_rpc_client::http_sender::HttpSender,
+    solana_sdk::{
+        account::Account, bs58, commitment_config::CommitmentConfig, program_pack::Pack,
+        pubkey::Pubkey, rent::Rent, signature::Signature,
+    },
+    solana_transaction_status::{
+        option_serializer::OptionSerializer, EncodedConfirmedTransactionWithStatusMeta,
+        EncodedTransaction, TransactionStatus, UiCompiledInstruction, UiInstruction,
+        UiLoadedAddresses, UiMessage, UiParsedInstruction, UiTransaction, UiTransactionEncoding,
+        UiTransactionTokenBalance,
+    },
+    spl_token::{instruction::TokenInstruction, state::Mint},
+    std::{
+        cell::Cell,
+        collections::{BTreeMap, VecDeque},
+        future::Future,
+        num::NonZeroUsize,
+        path::{Path, PathBuf},
+        pin::Pin,
+        str::FromStr,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc, Mutex,
+        },
+        task::{Context, Poll},
+        time::Instant,
+    },
+    storage::{JsonLinesStorage, Storage},
+    thiserror::Error,
+    tokio::{
+        fs::{File, OpenOptions},
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter},
+        sync::mpsc,
+        task::JoinSet,
+        time::{sleep, timeout, Duration},
+    },
+    tokio_stream::{wrappers::ReceiverStream, Stream},
+    tokio_util::sync::{CancellationToken, DropGuard},
+};
+
+/// AMM v4 pool-creation fee account, the same default `main.rs` watches.
+pub const RAY_FEE: &str = "7YttLkHDoNj9wyDur5pM1ejNaAvT9X4eqaYcHQqtj2G5";
+const LP_OWNER: &str = "5Q544fKrFoe6tsEbD7S8EmxGTJYAKtTVhAW5Q5pge4j1";
+const CPMM_AUTHORITY: &str = "GpMZbSM2GgvTKHJirzeGfMFoaZ8UR2X7F4v8vHTvxFbL";
+const AMM_V4_PROGRAM: &str = "675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8";
+const CPMM_PROGRAM: &str = "CPMMoo8L3F4NbTegBCKVNunggL7H1ZpdTHKxQB5qKP1C";
+/// Instruction tag of AMM v4 `initialize2`.
+const AMM_V4_INITIALIZE2_TAG: u8 = 1;
+/// Anchor discriminator of CPMM `initialize`: `sha256("global:initialize")[..8]`.
+const CPMM_INITIALIZE_DISCRIMINATOR: [u8; 8] = [175, 175, 109, 31, 13, 152, 155, 237];
+const WSOL_MINT: &str = "So11111111111111111111111111111111111111112";
+const USDC_MINT: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+const USDT_MINT: &str = "Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB";
+const SOL_DECIMALS: u8 = 9;
+const ERROR_LOG_PATH: &str = "error_new_lps_logs.txt";
+const SIGNATURE_STATUS_POLL_INTERVAL: Duration = Duration::from_millis(500);
+const SIGNATURE_STATUS_MAX_POLLS: u32 = 60;
+/// Intervals `print_stats` averages over.
+const STATS_WINDOW: usize = 12;
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+const BACKFILL_MAX_SIGNATURES: usize = 1_000;
+/// Most signatures one `getSignaturesForAddress` response holds.
+const SIGNATURES_PAGE_LIMIT: usize = 1_000;
+/// Per-request timeout of the RPC client, `HttpSender`'s own default.
+const RPC_TIMEOUT: Duration = Duration::from_secs(30);
+/// Distinct `getSignaturesForAddress` queries the signatures cache holds.
+const SIGNATURES_CACHE_CAPACITY: usize = 64;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TokenInfo {
+    pub address: String,
+    pub decimals: u8,
+    /// Raw integer amount in base units; the source of truth.
+    #[serde(default)]
+    pub amount: u64,
+    /// `amount` scaled by `decimals`, serialized as a string to keep precision.
+    #[serde(
+        serialize_with = "rust_decimal::serde::str::serialize",
+        deserialize_with = "decimal_from_str_or_number"
+    )]
+    pub lp_amount: Decimal,
+}
+
+/// Reads a decimal written as a string, or as a JSON number by releases that
+/// stored `lp_amount` as an f64.
+fn decimal_from_str_or_number<'de, D>(deserializer: D) -> Result<Decimal, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StrOrNumber {
+        Str(String),
+        Number(serde_json::Number),
+    }
+
+    let text = match StrOrNumber::deserialize(deserializer)? {
+        StrOrNumber::Str(text) => text,
+        StrOrNumber::Number(number) => number.to_string(),
+    };
+    Decimal::from_str(&text)
+        .or_else(|_| Decimal::from_scientific(&text))
+        .map_err(|_| serde::de::Error::custom(format!("invalid decimal '{}'", text)))
+}
+
+impl TokenInfo {
+    fn new(address: String, decimals: u8, amount: u64) -> Self {
+        Self {
+            address,
+            decimals,
+            amount,
+            lp_amount: Decimal::from_i128_with_scale(amount as i128, decimals as u32),
+        }
+    }
+}
+
+/// One detected pool, stored as a JSON line.
+///
+/// Output is byte-stable for the same pool: fields serialize in declaration
+/// order, `all_balances` is sorted by mint then amount, and nothing in the
+/// record is a map. New fields go at the end so existing diffs stay quiet.
+///
+/// Compatibility policy: fields added after the first release carry
+/// `#[serde(default)]`, so older records still load in a newer binary. Fields
+/// are never renamed or removed. Records written by a newer binary load in an
+/// older one under `SchemaMode::Lenient`, which ignores unknown fields;
+/// `SchemaMode::Strict` rejects them instead.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TokenData {
+    pub lp_signature: String,
+    /// Pool (AMM id) account created by the transaction.
+    #[serde(default)]
+    pub pool_address: String,
+    #[serde(default)]
+    pub creator: String,
+    /// When the pool was detected, in `MonitorConfig::timestamp_format`.
+    pub timestamp: String,
+    #[serde(default)]
+    pub slot: u64,
+    /// Unix seconds of the block the pool was created in. Recent transactions
+    /// often come back without one; the detection time is used instead and
+    /// `block_time_estimated` is set. `None` only in records written before that.
+    #[serde(default)]
+    pub block_time: Option<i64>,
+    #[serde(default)]
+    pub base_info: TokenInfo,
+    #[serde(default)]
+    pub quote_info: TokenInfo,
+    /// Every post-token-balance in the transaction, only filled when
+    /// `MonitorConfig::include_all_balances` is set.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub all_balances: Vec<TokenInfo>,
+    /// Both mints were checked to be real SPL Token mints. `None` when
+    /// `MonitorConfig::verify_mints` is off or the lookup failed.
+    #[serde(default)]
+    pub mint_verified: Option<bool>,
+    /// Every enabled enrichment step produced a value. False when a step failed
+    /// or the per-pool enrichment budget ran out first.
+    #[serde(default)]
+    pub enrichment_complete: bool,
+    /// The base mint was initialized in the pool-creation transaction itself,
+    /// i.e. a new token rather than a relisting.
+    #[serde(default)]
+    pub fresh_mint: bool,
+    /// Share of the base supply, in percent, held by its largest account. Only
+    /// looked up when `MonitorConfig::check_top_holder` is set.
+    #[serde(default)]
+    pub top_holder_pct: Option<f64>,
+    /// Whether the base mint still has a mint / freeze authority. Read along
+    /// with `mint_verified`.
+    #[serde(default)]
+    pub mint_authority_set: Option<bool>,
+    #[serde(default)]
+    pub freeze_authority_set: Option<bool>,
+    /// 0 to 100, see `RiskWeights`.
+    #[serde(default)]
+    pub risk_score: u8,
+    /// Label of the quote mint from `MonitorConfig::quote_labels`, e.g. "SOL",
+    /// or the mint itself if it has none.
+    #[serde(default)]
+    pub quote_symbol: String,
+    /// `block_time` is the detection time, not the block's, because the RPC
+    /// didn't return one. Anything measured from `block_time` is skipped then.
+    #[serde(default)]
+    pub block_time_estimated: bool,
+    /// `account_keys[0]`, who paid for the transaction. Same as `creator`.
+    #[serde(default)]
+    pub fee_payer: String,
+    /// Wallet named as the creator in the pool-creation instruction. Differs
+    /// from `fee_payer` when someone else pays for the transaction.
+    #[serde(default)]
+    pub initializer: Option<String>,
+    /// Compute units the pool-creation transaction used. `None` when the RPC
+    /// node is too old to report them.
+    #[serde(default)]
+    pub compute_units: Option<u64>,
+    /// Transaction fee paid by `fee_payer`, priority fee included.
+    #[serde(default)]
+    pub fee_lamports: u64,
+}
+
+#[derive(Error, Debug)]
+pub enum MonitorError {
+    #[error("Base and quote resolved to the same mint {0}")]
+    SameMint(String),
+    #[error("No {0} token balance owned by the pool authority")]
+    TokenInfoNotFound(&'static str),
+    #[error("Several {side} token balances owned by the pool authority: {first} and {second}")]
+    AmbiguousTokenInfo {
+        side: &'static str,
+        first: String,
+        second: String,
+    },
+    #[error("Token balance for {0} has no valid raw amount")]
+    InvalidAmount(String),
+    #[error("Retry budget exhausted, not retrying")]
+    RetryBudgetExhausted,
+    #[error("Log subscription gave up after {0} reconnects")]
+    ReconnectsExhausted(u32),
+    #[error("Refusing to send {0}: the RPC client is read-only")]
+    WriteBlocked(RpcRequest),
+    #[error("RPC call cap of {0} reached")]
+    RpcCallCapReached(u32),
+}
+
+/// RPC methods that change or simulate chain state. The monitor never needs them.
+const WRITE_RPC_METHODS: &[RpcRequest] = &[
+    RpcRequest::SendTransaction,
+    RpcRequest::SimulateTransaction,
+    RpcRequest::RequestAirdrop,
+];
+
+/// An `RpcClient` for `rpc_url` whose requests carry `config.user_agent` and,
+/// when set, `config.session_id` as `X-Session-Id`.
+fn http_rpc_client(rpc_url: &str, config: &MonitorConfig) -> Result<RpcClient> {
+    let mut headers = HttpSender::default_headers();
+    if let Some(session_id) = &config.session_id {
+        headers.insert("x-session-id", HeaderValue::from_str(session_id)?);
+    }
+    let client = reqwest::Client::builder()
+        .user_agent(&config.user_agent)
+        .default_headers(headers)
+        .timeout(RPC_TIMEOUT)
+        .pool_idle_timeout(RPC_TIMEOUT)
+        .build()?;
+    Ok(RpcClient::new_sender(
+        HttpSender::new_with_client(rpc_url, client),
+        RpcClientConfig::with_commitment(CommitmentConfig::confirmed()),
+    ))
+}
+
+/// `RpcClient` wrapper that only exposes reads.
+///
+/// Guarantee: nothing in the monitor can make a write RPC call. The typed
+/// methods here are all reads. The raw `send` escape hatch checks its method
+/// against `WRITE_RPC_METHODS` and refuses writes with
+/// `MonitorError::WriteBlocked`. The inner client is private, so it can't be
+/// used to get around the check.
+///
+/// Every call that reaches the network, and no cache hit, goes through
+/// `charge_rpc_call`, so the per-event and enrichment caps hold for all of them.
+pub struct ReadOnlyRpcClient {
+    inner: RpcClient,
+    account_cache: Option<AccountCache>,
+    signatures_cache: Option<SignaturesCache>,
+}
+
+/// Recently fetched accounts, each kept for `ttl` after it was fetched.
+struct AccountCache {
+    entries: Mutex<LruCache<Pubkey, (Instant, Account)>>,
+    ttl: Duration,
+}
+
+/// `getSignaturesForAddress` query: the address, its `before`/`until` cursor,
+/// the limit and the commitment.
+type SignaturesQuery = (
+    Pubkey,
+    Option<Signature>,
+    Option<Signature>,
+    Option<usize>,
+    Option<CommitmentConfig>,
+);
+
+type SignaturesPage = Vec<RpcConfirmedTransactionStatusWithSignature>;
+
+/// Recent `getSignaturesForAddress` responses, each kept for `ttl`.
+struct SignaturesCache {
+    entries: Mutex<LruCache<SignaturesQuery, (Instant, SignaturesPage)>>,
+    ttl: Duration,
+}
+
+impl ReadOnlyRpcClient {
+    pub fn new(inner: RpcClient) -> Self {
+        Self {
+            inner,
+            account_cache: None,
+            signatures_cache: None,
+        }
+    }
+
+    /// Serves repeat `get_account` calls from memory for `ttl`, holding at most
+    /// `capacity` accounts. A capacity of zero disables the cache.
+    pub fn with_account_cache(mut self, capacity: usize, ttl: Duration) -> Self {
+        self.account_cache = NonZeroUsize::new(capacity).map(|capacity| AccountCache {
+            entries: Mutex::new(LruCache::new(capacity)),
+            ttl,
+        });
+        self
+    }
+
+    /// Answers a repeat of the same `get_signatures_for_address_with_config`
+    /// query from memory for `ttl`, so monitors polling the same address
+    /// share one call. Keep `ttl` short; a zero `ttl` disables the cache.
+    pub fn with_signatures_cache(mut self, ttl: Duration) -> Self {
+        self.signatures_cache = (!ttl.is_zero()).then(|| SignaturesCache {
+            entries: Mutex::new(LruCache::new(
+                NonZeroUsize::new(SIGNATURES_CACHE_CAPACITY).unwrap(),
+            )),
+            ttl,
+        });
+        self
+    }
+
+    pub fn commitment(&self) -> CommitmentConfig {
+        self.inner.commitment()
+    }
+
+    pub async fn get_health(&self) -> Result<()> {
+        charge_rpc_call()?;
+        Ok(self.inner.get_health().await?)
+    }
+
+    pub async fn get_version(&self) -> Result<RpcVersionInfo> {
+        charge_rpc_call()?;
+        Ok(self.inner.get_version().await?)
+    }
+
+    pub async fn get_account(&self, pubkey: &Pubkey) -> Result<Account> {
+        let Some(cache) = &self.account_cache else {
+            charge_rpc_call()?;
+            return Ok(self.inner.get_account(pubkey).await?);
+        };
+
+        if let Some((fetched_at, account)) = cache.entries.lock().unwrap().get(pubkey) {
+            if fetched_at.elapsed() < cache.ttl {
+                return Ok(account.clone());
+            }
+        }
+
+        charge_rpc_call()?;
+        let account = self.inner.get_account(pubkey).await?;
+        cache
+            .entries
+            .lock()
+            .unwrap()
+            .put(*pubkey, (Instant::now(), account.clone()));
+        Ok(account)
+    }
+
+    /// Fetches several accounts in one `getMultipleAccounts` call, `None` for
+    /// any that don't exist. Cached accounts are left out of the request, and
+    /// no request is made when all of them are cached.
+    pub async fn get_multiple_accounts(&self, pubkeys: &[Pubkey]) -> Result<Vec<Option<Account>>> {
+        let Some(cache) = &self.account_cache else {
+            charge_rpc_call()?;
+            return Ok(self.inner.get_multiple_accounts(pubkeys).await?);
+        };
+
+        let mut accounts: Vec<Option<Account>> = {
+            let mut entries = cache.entries.lock().unwrap();
+            pubkeys
+                .iter()
+                .map(|pubkey| match entries.get(pubkey) {
+                    Some((fetched_at, account)) if fetched_at.elapsed() < cache.ttl => {
+                        Some(account.clone())
+                    }
+                    _ => None,
+                })
+                .collect()
+        };
+        let missing: Vec<usize> = (0..pubkeys.len())
+            .filter(|&i| accounts[i].is_none())
+            .collect();
+        if missing.is_empty() {
+            return Ok(accounts);
+        }
+
+        let request: Vec<Pubkey> = missing.iter().map(|&i| pubkeys[i]).collect();
+        charge_rpc_call()?;
+        let fetched = self.inner.get_multiple_accounts(&request).await?;
+        let mut entries = cache.entries.lock().unwrap();
+        for (i, account) in missing.into_iter().zip(fetched) {
+            if let Some(account) = &account {
+                entries.put(pubkeys[i], (Instant::now(), account.clone()));
+            }
+            accounts[i] = account;
+        }
+        Ok(accounts)
+    }
+
+    pub async fn get_transaction(
+        &self,
+        signature: &Signature,
+        config: RpcTransactionConfig,
+    ) -> Result<EncodedConfirmedTransactionWithStatusMeta> {
+        charge_rpc_call()?;
+        Ok(self
+            .inner
+            .get_transaction_with_config(signature, config)
+            .await?)
+    }
+
+    pub async fn get_token_largest_accounts(
+        &self,
+        mint: &Pubkey,
+    ) -> Result<Vec<RpcTokenAccountBalance>> {
+        charge_rpc_call()?;
+        Ok(self.inner.get_token_largest_accounts(mint).await?)
+    }
+
+    pub async fn get_token_supply(&self, mint: &Pubkey) -> Result<UiTokenAmount> {
+        charge_rpc_call()?;
+        Ok(self.inner.get_token_supply(mint).await?)
+    }
+
+    pub async fn get_signature_statuses(
+        &self,
+        signatures: &[Signature],
+    ) -> Result<Response<Vec<Option<TransactionStatus>>>> {
+        charge_rpc_call()?;
+        Ok(self.inner.get_signature_statuses(signatures).await?)
+    }
+
+    pub async fn get_signatures_for_address_with_config(
+        &self,
+        address: &Pubkey,
+        config: GetConfirmedSignaturesForAddress2Config,
+    ) -> Result<SignaturesPage> {
+        let Some(cache) = &self.signatures_cache else {
+            charge_rpc_call()?;
+            return Ok(self
+                .inner
+                .get_signatures_for_address_with_config(address, config)
+                .await?);
+        };
+
+        let query = (
+            *address,
+            config.before,
+            config.until,
+            config.limit,
+            config.commitment,
+        );
+        if let Some((fetched_at, page)) = cache.entries.lock().unwrap().get(&query) {
+            if fetched_at.elapsed() < cache.ttl {
+                return Ok(page.clone());
+            }
+        }
+
+        charge_rpc_call()?;
+        let page = self
+            .inner
+            .get_signatures_for_address_with_config(address, config)
+            .await?;
+        cache
+            .entries
+            .lock()
+            .unwrap()
+            .put(query, (Instant::now(), page.clone()));
+        Ok(page)
+    }
+
+    /// Raw JSON-RPC call for methods without a typed wrapper. Writes are refused.
+    pub async fn send<T: serde::de::DeserializeOwned>(
+        &self,
+        request: RpcRequest,
+        params: Value,
+    ) -> Result<T> {
+        ensure_read_only(request)?;
+        charge_rpc_call()?;
+        Ok(self.inner.send(request, params).await?)
+    }
+}
+
+tokio::task_local! {
+    /// `(cap, calls left)` for the detection running on this task.
+    static EVENT_RPC_CALLS: Cell<(u32, u32)>;
+}
+
+/// Counts one RPC call against the current detection's
+/// `max_rpc_calls_per_event`, refusing it once the cap is spent. Calls made
+/// outside a detection, like backfills, aren't counted.
+fn charge_rpc_call() -> Result<(), MonitorError> {
+    EVENT_RPC_CALLS
+        .try_with(|calls| match calls.get() {
+            (cap, 0) => Err(MonitorError::RpcCallCapReached(cap)),
+            (cap, left) => {
+                calls.set((cap, left - 1));
+                Ok(())
+            }
+        })
+        .unwrap_or(Ok(()))
+}
+
+/// Runs `work` with at most `cap` RPC calls. Inside a detection with its own
+/// cap the calls count against both, so `work` gets whichever has fewer left.
+async fn with_rpc_call_cap<T>(cap: u32, work: impl Future<Output = T>) -> T {
+    let outer = EVENT_RPC_CALLS.try_with(Cell::get).ok();
+    let inner = match outer {
+        Some((outer_cap, left)) if left < cap => (outer_cap, left),
+        _ => (cap, cap),
+    };
+    let (output, left) = EVENT_RPC_CALLS
+        .scope(Cell::new(inner), async {
+            let output = work.await;
+            (output, EVENT_RPC_CALLS.with(|calls| calls.get().1))
+        })
+        .await;
+
+    if let Some((outer_cap, outer_left)) = outer {
+        let used = inner.1 - left;
+        EVENT_RPC_CALLS.with(|calls| calls.set((outer_cap, outer_left - used)));
+    }
+    output
+}
+
+fn ensure_read_only(request: RpcRequest) -> Result<(), MonitorError> {
+    if WRITE_RPC_METHODS.contains(&request) {
+        return Err(MonitorError::WriteBlocked(request));
+    }
+    Ok(())
+}
+
+/// Direction of a hypothetical trade against a pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    /// Pay the quote token, receive the base token.
+    Buy,
+    /// Pay the base token, receive the quote token.
+    Sell,
+}
+
+/// Fraction (0..1) by which a trade of `amount_in` moves the execution price
+/// away from the spot price, using the constant-product formula on the pool's
+/// initial reserves. Fees are ignored.
+///
+/// With reserves `x` (in) and `y` (out), the trade gets
+/// `y * amount_in / (x + amount_in)` against a spot of `y / x`, which works out
+/// to an impact of `amount_in / (x + amount_in)`.
+pub fn price_impact(pool: &TokenData, amount_in: Decimal, direction: Side) -> Decimal {
+    let reserve_in = match direction {
+        Side::Buy => pool.quote_info.lp_amount,
+        Side::Sell => pool.base_info.lp_amount,
+    };
+
+    let denominator = reserve_in + amount_in;
+    if denominator.is_zero() {
+        return Decimal::ZERO;
+    }
+
+    amount_in / denominator
+}
+
+/// Whether `signature` falls in a `rate` (0.0 to 1.0) sample. Based on an FNV-1a
+/// hash of the signature, so the same signatures are picked on every run.
+fn is_sampled(signature: &Signature, rate: f64) -> bool {
+    const BUCKETS: u64 = 10_000;
+
+    if rate >= 1.0 {
+        return true;
+    }
+    let hash = signature
+        .as_ref()
+        .iter()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+        });
+    ((hash % BUCKETS) as f64) < rate * BUCKETS as f64
+}
+
+fn quote_symbol(labels: &BTreeMap<String, String>, mint: &str) -> String {
+    labels
+        .get(mint)
+        .cloned()
+        .unwrap_or_else(|| mint.to_string())
+}
+
+/// `part` as a percentage of `total`, `None` when there is no supply.
+fn holder_share_pct(part: u64, total: u64) -> Option<f64> {
+    if total == 0 {
+        return None;
+    }
+    Some(part as f64 / total as f64 * 100.0)
+}
+
+/// Top-level `TokenData` keys. Keep in sync with the struct.
+const TOKEN_DATA_FIELDS: &[&str] = &[
+    "lp_signature",
+    "pool_address",
+    "creator",
+    "timestamp",
+    "slot",
+    "block_time",
+    "base_info",
+    "quote_info",
+    "all_balances",
+    "mint_verified",
+    "enrichment_complete",
+    "fresh_mint",
+    "top_holder_pct",
+    "mint_authority_set",
+    "freeze_authority_set",
+    "risk_score",
+    "quote_symbol",
+    "block_time_estimated",
+    "fee_payer",
+    "initializer",
+    "compute_units",
+    "fee_lamports",
+];
+
+/// How `read_token_data` treats fields it doesn't know about.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SchemaMode {
+    /// Ignore unknown fields, e.g. ones written by a newer binary.
+    #[default]
+    Lenient,
+    /// Reject records with unknown top-level fields.
+    Strict,
+}
+
+/// Parses one stored record according to `mode`.
+pub fn read_token_data(line: &str, mode: SchemaMode) -> Result<TokenData> {
+    let value: Value = serde_json::from_str(line)?;
+
+    if mode == SchemaMode::Strict {
+        if let Some(object) = value.as_object() {
+            if let Some(unknown) = object
+                .keys()
+                .find(|key| !TOKEN_DATA_FIELDS.contains(&key.as_str()))
+            {
+                return Err(anyhow!("Unknown TokenData field '{}'", unknown));
+            }
+        }
+    }
+
+    Ok(serde_json::from_value(value)?)
+}
+
+/// Everything the monitor decided about a signature, so consumers can observe
+/// skips and failures as well as new pools.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DetectionEvent {
+    Created {
+        token_data: Box<TokenData>,
+        /// Milliseconds from `block_time` to the record being stored. `None`
+        /// when `block_time_estimated`.
+        detection_latency_ms: Option<i64>,
+    },
+    /// Emitted instead of `Created` in minimal mode.
+    Minimal(MinimalEvent),
+    Filtered {
+        signature: String,
+        reason: String,
+    },
+    Failed {
+        signature: String,
+        error: String,
+    },
+    /// What the configured `Strategy` decided about a stored pool. Nothing is
+    /// executed; a separate executor can act on it.
+    Decision {
+        signature: String,
+        decision: Decision,
+    },
+}
+
+/// A `Strategy`'s verdict on one pool.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Decision {
+    Buy {
+        #[serde(with = "rust_decimal::serde::str")]
+        sol_amount: Decimal,
+    },
+    Skip {
+        reason: String,
+    },
+}
+
+/// Decides what to do about each pool once it is enriched and stored.
+pub trait Strategy: Send + Sync {
+    fn on_pool(&self, data: &TokenData) -> Decision;
+}
+
+/// Buys a fixed amount of every pool quoted in SOL that has at least
+/// `min_sol_liquidity` SOL and a `risk_score` of at most `max_risk_score`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ThresholdStrategy {
+    #[serde(with = "rust_decimal::serde::str")]
+    pub sol_amount: Decimal,
+    #[serde(with = "rust_decimal::serde::str")]
+    pub min_sol_liquidity: Decimal,
+    pub max_risk_score: u8,
+}
+
+impl Strategy for ThresholdStrategy {
+    fn on_pool(&self, data: &TokenData) -> Decision {
+        let skip = |reason: String| Decision::Skip { reason };
+
+        if data.quote_info.address != WSOL_MINT {
+            return skip(format!("quoted in {}, not SOL", data.quote_symbol));
+        }
+        if data.quote_info.lp_amount < self.min_sol_liquidity {
+            return skip(format!(
+                "{} SOL liquidity, below {}",
+                data.quote_info.lp_amount, self.min_sol_liquidity
+            ));
+        }
+        if data.risk_score > self.max_risk_score {
+            return skip(format!(
+                "risk score {}, above {}",
+                data.risk_score, self.max_risk_score
+            ));
+        }
+
+        Decision::Buy {
+            sol_amount: self.sol_amount,
+        }
+    }
+}
+
+/// Just enough to say "a pool for this mint appeared", with no enrichment.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MinimalEvent {
+    pub signature: String,
+    pub slot: u64,
+    pub base_mint: String,
+}
+
+/// Shape of the stored pool records.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputSchema {
+    /// `TokenData` as is.
+    #[default]
+    Standard,
+    /// `main.rs`'s record: `lp_signature`, `creator`, `timestamp` and a
+    /// `tokens` array holding the base then the quote token. `tail`, `serve`
+    /// and the readers of the data file only understand `Standard` records.
+    Legacy,
+}
+
+impl FromStr for OutputSchema {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "standard" => Ok(OutputSchema::Standard),
+            "legacy" => Ok(OutputSchema::Legacy),
+            other => Err(anyhow!(
+                "Unknown output schema '{}', expected standard or legacy",
+                other
+            )),
+        }
+    }
+}
+
+/// A `TokenData` in `OutputSchema::Legacy` form.
+#[derive(Debug, Serialize)]
+struct LegacyTokenData<'a> {
+    lp_signature: &'a str,
+    creator: &'a str,
+    timestamp: &'a str,
+    tokens: [&'a TokenInfo; 2],
+}
+
+impl<'a> From<&'a TokenData> for LegacyTokenData<'a> {
+    fn from(data: &'a TokenData) -> Self {
+        Self {
+            lp_signature: &data.lp_signature,
+            creator: &data.creator,
+            timestamp: &data.timestamp,
+            tokens: [&data.base_info, &data.quote_info],
+        }
+    }
+}
+
+/// Which of stdout and the stores gets a record first when `tee_stdout` is set.
+/// The second write only happens once the first has succeeded.
+///
+/// A record counts as written only once every store has it; otherwise
+/// `store_record` fails and the signature is retried later. Both sinks are
+/// therefore at-least-once, and a crash or failure between the two writes
+/// leaves the record in the first sink only, until the retry repeats it there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TeeOrder {
+    /// Stores first, synced to disk. Every line on stdout is already durable.
+    FileFirst,
+    /// Stdout first. Every stored record was printed before it was stored.
+    StdoutFirst,
+}
+
+impl FromStr for TeeOrder {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "file_first" => Ok(TeeOrder::FileFirst),
+            "stdout_first" => Ok(TeeOrder::StdoutFirst),
+            other => Err(anyhow!(
+                "Unknown tee order '{}', expected file_first or stdout_first",
+                other
+            )),
+        }
+    }
+}
+
+/// What `parse_transaction` made of a signature.
+enum ParseOutcome {
+    Pool(Box<TokenData>),
+    /// The transaction was seen but deliberately not turned into a pool.
+    Skipped(String),
+}
+
+/// Raydium pool program a detection comes from. Each program owns its vaults
+/// through a different authority.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PoolVariant {
+    /// Legacy AMM v4; vaults are owned by the global `amm authority`
+    /// (`5Q544f...`).
+    #[default]
+    AmmV4,
+    /// Constant-product CPMM; vaults are owned by the program's
+    /// `vault_and_lp_mint_auth_seed` PDA (`GpMZbS...`).
+    Cpmm,
+}
+
+impl PoolVariant {
+    /// Owner expected on the pool's token-balance entries.
+    pub fn authority(&self) -> &'static str {
+        match self {
+            PoolVariant::AmmV4 => LP_OWNER,
+            PoolVariant::Cpmm => CPMM_AUTHORITY,
+        }
+    }
+
+    pub fn program_id(&self) -> &'static str {
+        match self {
+            PoolVariant::AmmV4 => AMM_V4_PROGRAM,
+            PoolVariant::Cpmm => CPMM_PROGRAM,
+        }
+    }
+
+    /// Position of the pool account in the pool-creation instruction's accounts:
+    /// `amm` in AMM v4 `initialize2`, `pool_state` in CPMM `initialize`.
+    fn pool_account_index(&self) -> usize {
+        match self {
+            PoolVariant::AmmV4 => 4,
+            PoolVariant::Cpmm => 3,
+        }
+    }
+
+    /// Positions of each side's mint and the pool vault holding it, in the
+    /// pool-creation instruction's accounts: coin then pc for AMM v4
+    /// `initialize2`, token 0 then token 1 for CPMM `initialize`.
+    fn vault_account_indexes(&self) -> [(usize, usize); 2] {
+        match self {
+            PoolVariant::AmmV4 => [(8, 10), (9, 11)],
+            PoolVariant::Cpmm => [(4, 10), (5, 11)],
+        }
+    }
+
+    /// Position of the wallet that creates the pool, which need not be the fee
+    /// payer: `user_wallet` in AMM v4 `initialize2`, `creator` in CPMM `initialize`.
+    fn initializer_account_index(&self) -> usize {
+        match self {
+            PoolVariant::AmmV4 => 17,
+            PoolVariant::Cpmm => 0,
+        }
+    }
+
+    fn is_pool_creation(&self, data: &[u8]) -> bool {
+        match self {
+            PoolVariant::AmmV4 => data.first() == Some(&AMM_V4_INITIALIZE2_TAG),
+            PoolVariant::Cpmm => data.starts_with(&CPMM_INITIALIZE_DISCRIMINATOR),
+        }
+    }
+}
+
+/// Points each rug signal adds to `TokenData::risk_score`, which is capped at
+/// 100. Signals that weren't looked up (`None`) add nothing, so the score is
+/// only as complete as the enrichment behind it:
+///
+/// - `mint_authority`: the base mint can still be minted (needs `verify_mints`).
+/// - `freeze_authority`: holders can be frozen (needs `verify_mints`).
+/// - `top_holder`: one account holds at least `top_holder_pct` percent of the
+///   supply (needs `check_top_holder`).
+/// - `low_liquidity`: less than `min_quote_liquidity` of the quote token,
+///   in whole tokens, went into the pool.
+/// - `fresh_mint`: the base token was created in the pool transaction.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RiskWeights {
+    pub mint_authority: u8,
+    pub freeze_authority: u8,
+    pub top_holder: u8,
+    pub top_holder_pct: f64,
+    pub low_liquidity: u8,
+    #[serde(with = "rust_decimal::serde::str")]
+    pub min_quote_liquidity: Decimal,
+    pub fresh_mint: u8,
+}
+
+impl Default for RiskWeights {
+    fn default() -> Self {
+        Self {
+            mint_authority: 30,
+            freeze_authority: 30,
+            top_holder: 20,
+            top_holder_pct: 50.0,
+            low_liquidity: 10,
+            min_quote_liquidity: Decimal::from(10),
+            fresh_mint: 10,
+        }
+    }
+}
+
+impl RiskWeights {
+    pub fn score(&self, data: &TokenData) -> u8 {
+        let signals = [
+            (data.mint_authority_set == Some(true), self.mint_authority),
+            (
+                data.freeze_authority_set == Some(true),
+                self.freeze_authority,
+            ),
+            (
+                data.top_holder_pct
+                    .is_some_and(|pct| pct >= self.top_holder_pct),
+                self.top_holder,
+            ),
+            (
+                data.quote_info.lp_amount < self.min_quote_liquidity,
+                self.low_liquidity,
+            ),
+            (data.fresh_mint, self.fresh_mint),
+        ];
+
+        let total: u32 = signals
+            .iter()
+            .filter(|(present, _)| *present)
+            .map(|(_, weight)| *weight as u32)
+            .sum();
+        total.min(100) as u8
+    }
+}
+
+/// Time one pool's enrichment may still spend. Its RPC calls are capped by
+/// `with_rpc_call_cap`, which only counts calls that reach the network.
+struct EnrichmentBudget {
+    deadline: Instant,
+}
+
+impl EnrichmentBudget {
+    fn new(time: Duration) -> Self {
+        Self {
+            deadline: Instant::now() + time,
+        }
+    }
+
+    /// Runs a step, or returns `None` if the time runs out before it finishes.
+    async fn run<T>(&self, step: impl Future<Output = T>) -> Option<T> {
+        let remaining = self.deadline.saturating_duration_since(Instant::now());
+        timeout(remaining, step).await.ok()
+    }
+}
+
+/// How `TokenData::timestamp` is written. It is always a JSON string; the unix
+/// variants hold the number as digits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimestampFormat {
+    /// `2024-06-01T12:00:00.123456789+00:00`
+    #[default]
+    Rfc3339,
+    UnixSeconds,
+    UnixMillis,
+}
+
+impl TimestampFormat {
+    pub fn format(self, time: DateTime<Utc>) -> String {
+        match self {
+            TimestampFormat::Rfc3339 => time.to_rfc3339(),
+            TimestampFormat::UnixSeconds => time.timestamp().to_string(),
+            TimestampFormat::UnixMillis => time.timestamp_millis().to_string(),
+        }
+    }
+}
+
+impl FromStr for TimestampFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "rfc3339" => Ok(TimestampFormat::Rfc3339),
+            "unix" | "unix_seconds" => Ok(TimestampFormat::UnixSeconds),
+            "unix_millis" => Ok(TimestampFormat::UnixMillis),
+            other => Err(anyhow!(
+                "Unknown timestamp format '{}', expected rfc3339, unix_seconds or unix_millis",
+                other
+            )),
+        }
+    }
+}
+
+/// Reads a timestamp written in any `TimestampFormat`, so files written with
+/// different settings can be read together. Unix values of 10^11 (the year
+/// 5138 in seconds) or more are taken as milliseconds.
+pub fn parse_timestamp(timestamp: &str) -> Option<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(timestamp) {
+        return Some(time.with_timezone(&Utc));
+    }
+
+    let unix: i64 = timestamp.parse().ok()?;
+    if unix.abs() >= 100_000_000_000 {
+        DateTime::from_timestamp_millis(unix)
+    } else {
+        DateTime::from_timestamp(unix, 0)
+    }
+}
+
+/// Source of wall-clock time, so time-dependent logic can be driven in tests.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+#[derive(Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// Clock that only moves when told to.
+#[cfg(any(test, feature = "test-util"))]
+#[derive(Debug)]
+pub struct MockClock {
+    now: Mutex<DateTime<Utc>>,
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl MockClock {
+    pub fn new(start: DateTime<Utc>) -> Self {
+        Self {
+            now: Mutex::new(start),
+        }
+    }
+
+    pub fn advance(&self, by: chrono::Duration) {
+        *self.now.lock().unwrap() += by;
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.now.lock().unwrap() = now;
+    }
+}
+
+#[cfg(any(test, feature = "test-util"))]
+impl Clock for MockClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().unwrap()
+    }
+}
+
+/// Stream returned by `TokenMonitor::detections`; cancels the monitor when dropped.
+struct DetectionStream {
+    inner: ReceiverStream<DetectionEvent>,
+    _cancel_on_drop: DropGuard,
+}
+
+impl Stream for DetectionStream {
+    type Item = DetectionEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.inner).poll_next(cx)
+    }
+}
+
+/// Allowance of retries shared by every retry site in the process: at most
+/// `max_retries` per `window`. Once it's spent, callers fail fast instead of
+/// adding more load to an RPC that is already struggling.
+pub struct RetryBudget {
+    max_retries: u32,
+    window: Duration,
+    /// Start of the current window and retries used in it.
+    state: Mutex<(Instant, u32)>,
+}
+
+impl RetryBudget {
+    pub fn new(max_retries: u32, window: Duration) -> Self {
+        Self {
+            max_retries,
+            window,
+            state: Mutex::new((Instant::now(), 0)),
+        }
+    }
+
+    /// Takes one retry from the budget, or errors if the window's budget is spent.
+    pub fn acquire(&self) -> Result<(), MonitorError> {
+        let mut state = self.state.lock().unwrap();
+        let (window_start, used) = &mut *state;
+
+        if window_start.elapsed() >= self.window {
+            *window_start = Instant::now();
+            *used = 0;
+        }
+        if *used >= self.max_retries {
+            return Err(MonitorError::RetryBudgetExhausted);
+        }
+
+        *used += 1;
+        Ok(())
+    }
+}
+
+/// Counters behind the `stats_interval` printer. Everything but `queued` is
+/// reset each time a sample is taken.
+#[derive(Debug, Default)]
+struct DetectionStats {
+    pools: AtomicU64,
+    errors: AtomicU64,
+    enrichments: AtomicU64,
+    enrichment_micros: AtomicU64,
+    /// Notifications waiting in the processing queue right now.
+    queued: AtomicU64,
+}
+
+/// What `DetectionStats` counted during one interval.
+#[derive(Debug, Clone, Copy, Default)]
+struct StatsSample {
+    pools: u64,
+    errors: u64,
+    enrichments: u64,
+    enrichment_micros: u64,
+}
+
+impl DetectionStats {
+    fn record_enrichment(&self, elapsed: Duration) {
+        self.enrichments.fetch_add(1, Ordering::Relaxed);
+        self.enrichment_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    fn take_sample(&self) -> StatsSample {
+        StatsSample {
+            pools: self.pools.swap(0, Ordering::Relaxed),
+            errors: self.errors.swap(0, Ordering::Relaxed),
+            enrichments: self.enrichments.swap(0, Ordering::Relaxed),
+            enrichment_micros: self.enrichment_micros.swap(0, Ordering::Relaxed),
+        }
+    }
+}
+
+/// Rates averaged over a window of equally long `StatsSample`s.
+#[derive(Debug, PartialEq)]
+struct RollingStats {
+    pools_per_sec: f64,
+    errors_per_sec: f64,
+    avg_enrichment_ms: f64,
+}
+
+impl RollingStats {
+    fn over(window: &VecDeque<StatsSample>, interval: Duration) -> Self {
+        let total = window
+            .iter()
+            .fold(StatsSample::default(), |total, sample| StatsSample {
+                pools: total.pools + sample.pools,
+                errors: total.errors + sample.errors,
+                enrichments: total.enrichments + sample.enrichments,
+                enrichment_micros: total.enrichment_micros + sample.enrichment_micros,
+            });
+        let secs = interval.as_secs_f64() * window.len().max(1) as f64;
+
+        Self {
+            pools_per_sec: total.pools as f64 / secs,
+            errors_per_sec: total.errors as f64 / secs,
+            avg_enrichment_ms: if total.enrichments == 0 {
+                0.0
+            } else {
+                total.enrichment_micros as f64 / total.enrichments as f64 / 1_000.0
+            },
+        }
+    }
+}
+
+/// Why a single subscription stopped streaming.
+enum StreamEnd {
+    Cancelled,
+    Closed,
+}
+
+/// `top_holder_pct` by mint, with when it was looked up.
+type TopHolderCache = Mutex<LruCache<String, (DateTime<Utc>, Option<f64>)>>;
+
+pub struct TokenMonitor {
+    rpc_client: ReadOnlyRpcClient,
+    ws_url: String,
+    config: MonitorConfig,
+    /// Every detection is written to each of these.
+    stores: Vec<Box<dyn Storage>>,
+    strategy: Option<Box<dyn Strategy>>,
+    events: Option<mpsc::Sender<DetectionEvent>>,
+    /// Keys of detections already emitted, per `MonitorConfig::dedup_key`,
+    /// bounded by `dedup_capacity`.
+    seen: Mutex<LruCache<String, ()>>,
+    /// Signatures already taken up by `handle_signature` from any source, so a
+    /// transaction announced at several commitments or by both the stream and
+    /// a backfill is fetched once. Bounded by `dedup_capacity`.
+    handled_signatures: Mutex<LruCache<Signature, ()>>,
+    clock: Arc<dyn Clock>,
+    stats: DetectionStats,
+    retry_budget: Arc<RetryBudget>,
+    /// Durable copy of every emitted event, when `wal_path` is set.
+    event_log: Option<EventLog>,
+    /// Recent `top_holder_pct` lookups by mint, expiring `account_cache_ttl`
+    /// after they were made by `clock`.
+    top_holder_cache: Option<TopHolderCache>,
+    /// Where `tee_stdout` writes records; stdout unless replaced.
+    record_output: Mutex<Box<dyn std::io::Write + Send>>,
+}
+
+impl TokenMonitor {
+    pub async fn new(
+        rpc_url: &str,
+        ws_url: &str,
+        data_path: PathBuf,
+        config: MonitorConfig,
+    ) -> Result<Self> {
+        let rpc_client = http_rpc_client(rpc_url, &config)?;
+        Self::with_rpc_client(rpc_client, ws_url, data_path, config).await
+    }
+
+    /// `new` over an existing client, e.g. one with a custom `RpcSender`.
+    pub async fn with_rpc_client(
+        rpc_client: RpcClient,
+        ws_url: &str,
+        data_path: PathBuf,
+        config: MonitorConfig,
+    ) -> Result<Self> {
+        // `mpsc::channel(0)` panics and no workers would never drain the queue.
+        if config.queue_capacity == 0 || config.workers == 0 {
+            return Err(anyhow!("queue_capacity and workers must be at least 1"));
+        }
+        let dedup_capacity = NonZeroUsize::new(config.dedup_capacity)
+            .ok_or_else(|| anyhow!("dedup_capacity must be at least 1"))?;
+        let retry_budget = Arc::new(RetryBudget::new(config.retry_budget, config.retry_window));
+        let event_log = match &config.wal_path {
+            Some(path) => Some(EventLog::open(path).await?),
+            None => None,
+        };
+        let top_holder_cache = NonZeroUsize::new(config.account_cache_capacity)
+            .map(|capacity| Mutex::new(LruCache::new(capacity)));
+        Ok(Self {
+            rpc_client: ReadOnlyRpcClient::new(rpc_client)
+                .with_account_cache(config.account_cache_capacity, config.account_cache_ttl)
+                .with_signatures_cache(config.signatures_cache_ttl),
+            ws_url: ws_url.to_string(),
+            stores: vec![Box::new(JsonLinesStorage::new(data_path, &config))],
+            strategy: config
+                .threshold_strategy
+                .clone()
+                .map(|strategy| Box::new(strategy) as Box<dyn Strategy>),
+            config,
+            events: None,
+            seen: Mutex::new(LruCache::new(dedup_capacity)),
+            handled_signatures: Mutex::new(LruCache::new(dedup_capacity)),
+            clock: Arc::new(SystemClock),
+            stats: DetectionStats::default(),
+            retry_budget,
+            event_log,
+            top_holder_cache,
+            record_output: Mutex::new(Box::new(std::io::stdout())),
+        })
+    }
+
+    /// Runs `strategy` on every stored pool and emits its `Decision`. The
+    /// binary sets `threshold_strategy` from the config instead; this is for
+    /// code embedding the monitor.
+    pub fn set_strategy(&mut self, strategy: Box<dyn Strategy>) {
+        self.strategy = Some(strategy);
+    }
+
+    /// Sends the records `tee_stdout` prints to `output` instead of stdout.
+    #[cfg(any(test, feature = "test-util"))]
+    pub fn set_record_output(&mut self, output: Box<dyn std::io::Write + Send>) {
+        self.record_output = Mutex::new(output);
+    }
+
+    /// Writes detections to `store` as well as the stores already configured.
+    pub fn add_store(&mut self, store: Box<dyn Storage>) {
+        self.stores.push(store);
+    }
+
+    /// Runs the monitor in a background task and yields its events as a stream.
+    /// Dropping the stream cancels the monitor. Not used by the binary itself.
+    pub fn detections(mut self, capacity: usize) -> impl Stream<Item = DetectionEvent> {
+        let receiver = self.subscribe_events(capacity);
+        let cancel = CancellationToken::new();
+        let monitor_cancel = cancel.clone();
+
+        tokio::spawn(async move {
+            if let Err(err) = Arc::new(self).monitor_new_tokens(monitor_cancel).await {
+                error!("Monitor stopped: {}", err);
+            }
+        });
+
+        DetectionStream {
+            inner: ReceiverStream::new(receiver),
+            _cancel_on_drop: cancel.drop_guard(),
+        }
+    }
+
+    /// Replaces the wall clock used for timestamps, date partitioning and the
+    /// expiry of cached `top_holder_pct` lookups.
+    #[cfg(any(test, feature = "test-util"))]
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// Returns a receiver of every `DetectionEvent`. The channel is bounded, so a
+    /// slow consumer applies backpressure to the monitor.
+    pub fn subscribe_events(&mut self, capacity: usize) -> mpsc::Receiver<DetectionEvent> {
+        let (sender, receiver) = mpsc::channel(capacity);
+        self.events = Some(sender);
+        receiver
+    }
+
+    async fn parse_transaction(&self, signature: &Signature) -> Result<ParseOutcome> {
+        if self.config.await_signature_status {
+            self.wait_for_signature_status(signature).await?;
+        }
+
+        let config = RpcTransactionConfig {
+            max_supported_transaction_version: Some(0),
+            commitment: Some(self.config.fetch_commitment),
+            encoding: Some(UiTransactionEncoding::Json), // Specify the encoding here
+        };
+
+        let transaction = self.fetch_transaction(signature, config).await?;
+
+        info!("Successfully parsed transaction {:?}", transaction);
+
+        // Take meta out once; minimal responses can omit it entirely.
+        let meta = match transaction.transaction.meta {
+            Some(meta) => meta,
+            None => {
+                return Ok(ParseOutcome::Skipped(
+                    "transaction has no metadata".to_string(),
+                ))
+            }
+        };
+        if meta.err.is_some() {
+            return Ok(ParseOutcome::Skipped("transaction failed".to_string()));
+        }
+
+        let (account_keys, instructions) = message_parts(transaction.transaction.transaction)?;
+
+        if let Some(missing) = missing_mention(
+            &self.config.required_mentions,
+            &account_keys,
+            &meta.loaded_addresses,
+        ) {
+            return Ok(ParseOutcome::Skipped(format!(
+                "does not mention {}",
+                missing
+            )));
+        }
+
+        let signer = account_keys
+            .first()
+            .ok_or_else(|| anyhow!("No signer found"))?
+            .to_string();
+
+        info!("Creator: {}", signer);
+
+        // `Skip` (field absent) and `None` both mean there are no token balances.
+        let post_token_balances: Vec<UiTransactionTokenBalance> =
+            Option::from(meta.post_token_balances).unwrap_or_default();
+        // Same shape: nodes that predate the field send neither value.
+        let compute_units: Option<u64> = meta.compute_units_consumed.into();
+
+        let authority = self.config.variant.authority();
+        let quote_mints = &self.config.quote_mints;
+        let base_info =
+            Self::extract_token_info(&post_token_balances, false, authority, quote_mints)?;
+        // Pools quoted in native SOL have no WSOL token balance; fall back to
+        // the lamports of the WSOL vault.
+        let quote_info =
+            match Self::extract_token_info(&post_token_balances, true, authority, quote_mints) {
+                Ok(info) => info,
+                Err(MonitorError::TokenInfoNotFound(_)) => Self::extract_native_quote_info(
+                    self.config.variant,
+                    &account_keys,
+                    &instructions,
+                    &meta.pre_balances,
+                    &meta.post_balances,
+                )?,
+                Err(err) => return Err(err.into()),
+            };
+
+        if base_info.address == quote_info.address {
+            return Err(MonitorError::SameMint(base_info.address).into());
+        }
+        if let Some(min) = self.config.min_liquidity {
+            if quote_info.lp_amount < min {
+                return Ok(ParseOutcome::Skipped(format!(
+                    "{} {} liquidity, below {}",
+                    quote_info.lp_amount,
+                    quote_symbol(&self.config.quote_labels, &quote_info.address),
+                    min
+                )));
+            }
+        }
+
+        let variant = self.config.variant;
+        let pool_address = match pool_creation_account(
+            variant,
+            &account_keys,
+            &instructions,
+            variant.pool_account_index(),
+        ) {
+            Some(pool_address) => pool_address,
+            None => {
+                return Ok(ParseOutcome::Skipped(
+                    "no pool-creation instruction".to_string(),
+                ))
+            }
+        };
+        let initializer = pool_creation_account(
+            variant,
+            &account_keys,
+            &instructions,
+            variant.initializer_account_index(),
+        );
+
+        let fresh_mint = initializes_mint(&account_keys, &instructions, &base_info.address);
+        if self.config.require_fresh_mint && !fresh_mint {
+            return Ok(ParseOutcome::Skipped(
+                "base mint was not created in this transaction".to_string(),
+            ));
+        }
+
+        // Claimed before enrichment so duplicates cost no RPC calls. Given back
+        // below if a filter drops the pool, and by `process_signature` if the
+        // pool isn't stored.
+        let dedup_key = self.dedup_key(&signature.to_string(), &base_info.address, &pool_address);
+        if self
+            .seen
+            .lock()
+            .unwrap()
+            .put(dedup_key.clone(), ())
+            .is_some()
+        {
+            return Ok(ParseOutcome::Skipped(format!(
+                "duplicate {:?} {}",
+                self.config.dedup_key, dedup_key
+            )));
+        }
+
+        let all_balances = if self.config.include_all_balances {
+            Self::all_balances(signature, &post_token_balances)
+        } else {
+            Vec::new()
+        };
+
+        let now = self.clock.now();
+        let mut token_data = TokenData {
+            lp_signature: signature.to_string(),
+            pool_address,
+            fee_payer: signer.clone(),
+            initializer,
+            compute_units,
+            fee_lamports: meta.fee,
+            creator: signer,
+            timestamp: self.config.timestamp_format.format(now),
+            slot: transaction.slot,
+            block_time: Some(transaction.block_time.unwrap_or_else(|| now.timestamp())),
+            block_time_estimated: transaction.block_time.is_none(),
+            quote_symbol: quote_symbol(&self.config.quote_labels, &quote_info.address),
+            base_info,
+            quote_info,
+            all_balances,
+            mint_verified: None,
+            enrichment_complete: false,
+            fresh_mint,
+            top_holder_pct: None,
+            mint_authority_set: None,
+            freeze_authority_set: None,
+            risk_score: 0,
+        };
+        // Minimal mode wants only what the transaction itself says.
+        if !self.config.minimal {
+            let started = Instant::now();
+            self.enrich(&mut token_data).await;
+            self.stats.record_enrichment(started.elapsed());
+        }
+
+        token_data.risk_score = self.config.risk_weights.score(&token_data);
+        if let Some(reason) = self.enriched_rejection(&token_data) {
+            self.seen.lock().unwrap().pop(&dedup_key);
+            return Ok(ParseOutcome::Skipped(reason));
+        }
+
+        Ok(ParseOutcome::Pool(Box::new(token_data)))
+    }
+
+    /// Why the filters that need enrichment drop `data`, if they do.
+    fn enriched_rejection(&self, data: &TokenData) -> Option<String> {
+        if let Some(max) = self.config.max_risk_score {
+            if data.risk_score > max {
+                return Some(format!("risk score {} above {}", data.risk_score, max));
+            }
+        }
+
+        if let (Some(max), Some(pct)) = (self.config.max_top_holder_pct, data.top_holder_pct) {
+            if pct > max {
+                return Some(format!(
+                    "top holder has {:.2}% of supply, above {:.2}%",
+                    pct, max
+                ));
+            }
+        }
+
+        None
+    }
+
+    /// Loads the transaction from `replay_dir` when set, otherwise from RPC,
+    /// saving a copy into `record_dir` when that is set.
+    async fn fetch_transaction(
+        &self,
+        signature: &Signature,
+        config: RpcTransactionConfig,
+    ) -> Result<EncodedConfirmedTransactionWithStatusMeta> {
+        if let Some(replay_dir) = &self.config.replay_dir {
+            let json = tokio::fs::read(fixture_path(replay_dir, signature)).await?;
+            return Ok(serde_json::from_slice(&json)?);
+        }
+
+        let transaction = self.rpc_client.get_transaction(signature, config).await?;
+
+        if let Some(record_dir) = &self.config.record_dir {
+            tokio::fs::create_dir_all(record_dir).await?;
+            let json = serde_json::to_vec_pretty(&transaction)?;
+            tokio::fs::write(fixture_path(record_dir, signature), json).await?;
+        }
+
+        Ok(transaction)
+    }
+
+    /// Optional extra lookups on top of what the transaction itself contains.
+    /// Best-effort: a failed lookup is logged and leaves its field `None`, the
+    /// pool is still stored. Steps run in order until the per-pool time or RPC
+    /// call cap is spent; whatever is left over stays `None`.
+    async fn enrich(&self, data: &mut TokenData) {
+        with_rpc_call_cap(
+            self.config.enrichment_max_rpc_calls,
+            self.enrich_steps(data),
+        )
+        .await;
+    }
+
+    async fn enrich_steps(&self, data: &mut TokenData) {
+        let budget = EnrichmentBudget::new(self.config.enrichment_timeout);
+        let mut complete = true;
+
+        if self.config.verify_mints {
+            let mints = [
+                data.base_info.address.as_str(),
+                data.quote_info.address.as_str(),
+            ];
+            match budget.run(self.verify_mints(&mints)).await {
+                Some(Ok(mints)) => {
+                    let [base_mint, quote_mint]: [Option<Mint>; 2] =
+                        mints.try_into().expect("one result per mint");
+                    data.mint_verified = Some(base_mint.is_some() && quote_mint.is_some());
+                    if let Some(base_mint) = base_mint {
+                        data.mint_authority_set = Some(base_mint.mint_authority.is_some());
+                        data.freeze_authority_set = Some(base_mint.freeze_authority.is_some());
+                    }
+                }
+                Some(Err(err)) => {
+                    warn!(
+                        "Mint verification failed for {}: {}",
+                        data.lp_signature, err
+                    );
+                    complete = false;
+                }
+                None => complete = false,
+            }
+        }
+
+        if self.config.check_top_holder {
+            let cached = self.cached_top_holder_pct(&data.base_info.address);
+            let pct = match cached {
+                Some(pct) => Some(Ok(pct)),
+                None => {
+                    budget
+                        .run(self.top_holder_pct(&data.base_info.address))
+                        .await
+                }
+            };
+            match pct {
+                Some(Ok(pct)) => {
+                    data.top_holder_pct = pct;
+                    if cached.is_none() {
+                        self.cache_top_holder_pct(&data.base_info.address, pct);
+                    }
+                }
+                Some(Err(err)) => {
+                    warn!(
+                        "Top holder lookup failed for {}: {}",
+                        data.lp_signature, err
+                    );
+                    complete = false;
+                }
+                None => complete = false,
+            }
+        }
+
+        if !complete {
+            info!("Enrichment of {} is partial", data.lp_signature);
+        }
+        data.enrichment_complete = complete;
+    }
+
+    /// Percentage of `mint`'s supply in its largest token account, or `None`
+    /// for a mint with no supply.
+    async fn top_holder_pct(&self, mint: &str) -> Result<Option<f64>> {
+        let mint = Pubkey::from_str(mint)?;
+        let largest = self.rpc_client.get_token_largest_accounts(&mint).await?;
+        let supply = self.rpc_client.get_token_supply(&mint).await?;
+
+        let largest = match largest.first() {
+            Some(account) => account.amount.amount.parse()?,
+            None => 0,
+        };
+        Ok(holder_share_pct(largest, supply.amount.parse()?))
+    }
+
+    fn cached_top_holder_pct(&self, mint: &str) -> Option<Option<f64>> {
+        let mut cache = self.top_holder_cache.as_ref()?.lock().unwrap();
+        let (fetched_at, pct) = cache.get(mint)?;
+        // A clock that went backwards counts as expired.
+        let age = self.clock.now().signed_duration_since(*fetched_at).to_std();
+        age.is_ok_and(|age| age < self.config.account_cache_ttl)
+            .then_some(*pct)
+    }
+
+    fn cache_top_holder_pct(&self, mint: &str, pct: Option<f64>) {
+        if let Some(cache) = &self.top_holder_cache {
+            cache
+                .lock()
+                .unwrap()
+                .put(mint.to_string(), (self.clock.now(), pct));
+        }
+    }
+
+    /// The `Mint` state of each of `mints`, fetched in one `getMultipleAccounts`
+    /// call. An entry is `None` if its account doesn't exist, isn't owned by the
+    /// SPL Token program or doesn't unpack as a mint.
+    async fn verify_mints(&self, mints: &[&str]) -> Result<Vec<Option<Mint>>> {
+        let pubkeys = mints
+            .iter()
+            .map(|mint| Pubkey::from_str(mint))
+            .collect::<Result<Vec<_>, _>>()?;
+        let accounts = self.rpc_client.get_multiple_accounts(&pubkeys).await?;
+
+        Ok(accounts
+            .into_iter()
+            .map(|account| {
+                let account = account.filter(|account| account.owner == spl_token::id())?;
+                Mint::unpack(&account.data).ok()
+            })
+            .collect())
+    }
+
+    /// Polls `get_signature_statuses` until the signature satisfies
+    /// `fetch_commitment`, so `get_transaction` isn't called before it can be found.
+    async fn wait_for_signature_status(&self, signature: &Signature) -> Result<()> {
+        let commitment = self.config.fetch_commitment;
+
+        for _ in 0..SIGNATURE_STATUS_MAX_POLLS {
+            let statuses = self
+                .rpc_client
+                .get_signature_statuses(&[*signature])
+                .await?
+                .value;
+
+            if let Some(Some(status)) = statuses.into_iter().next() {
+                if status.satisfies_commitment(commitment) {
+                    return Ok(());
+                }
+            }
+
+            self.retry_budget.acquire()?;
+            sleep(SIGNATURE_STATUS_POLL_INTERVAL).await;
+        }
+
+        Err(anyhow!(
+            "Signature {} did not reach {:?} commitment",
+            signature,
+            commitment.commitment
+        ))
+    }
+
+    /// Polls `get_signature_statuses` until the signature has at least
+    /// `min_confirmations`. A `None` count means the slot is rooted, which is
+    /// past any depth worth asking for.
+    async fn wait_for_confirmations(&self, signature: &Signature) -> Result<()> {
+        let min_confirmations = self.config.min_confirmations;
+
+        for _ in 0..SIGNATURE_STATUS_MAX_POLLS {
+            let statuses = self
+                .rpc_client
+                .get_signature_statuses(&[*signature])
+                .await?
+                .value;
+
+            if let Some(Some(status)) = statuses.into_iter().next() {
+                match status.confirmations {
+                    None => return Ok(()),
+                    Some(confirmations) if confirmations as u64 >= min_confirmations => {
+                        return Ok(())
+                    }
+                    Some(_) => {}
+                }
+            }
+
+            self.retry_budget.acquire()?;
+            sleep(SIGNATURE_STATUS_POLL_INTERVAL).await;
+        }
+
+        Err(anyhow!(
+            "Signature {} did not reach {} confirmations",
+            signature,
+            min_confirmations
+        ))
+    }
+
+    /// Picks the single base or quote balance owned by `authority`. The quote
+    /// side is the balance whose mint comes first in `quote_mints`, so the
+    /// returned `address` says which quote matched. The base side is any other
+    /// mint, so a SOL/USDC pool is quoted in SOL and USDC is its base.
+    ///
+    /// - No matching balance gives `TokenInfoNotFound`.
+    /// - Matches with different mints give `AmbiguousTokenInfo`. The same mint
+    ///   listed twice is fine, and the first entry wins.
+    /// - An entry without a parseable raw `amount` gives `InvalidAmount`.
+    ///   `uiAmount` is never read, so a null `uiAmount` doesn't matter.
+    /// - An entry with no owner is skipped.
+    fn extract_token_info(
+        balances: &[UiTransactionTokenBalance],
+        is_quote: bool,
+        authority: &str,
+        quote_mints: &[String],
+    ) -> Result<TokenInfo, MonitorError> {
+        let side = if is_quote { "quote" } else { "base" };
+
+        // Balances without an owner can't belong to the pool authority.
+        let owned = || {
+            balances
+                .iter()
+                .filter(|balance| balance_owner(balance) == Some(authority))
+        };
+        let quote_mint = owned()
+            .filter_map(|balance| {
+                let priority = quote_mints.iter().position(|mint| *mint == balance.mint)?;
+                Some((priority, &balance.mint))
+            })
+            .min()
+            .map(|(_, mint)| mint);
+
+        let mut candidates =
+            owned().filter(|balance| (Some(&balance.mint) == quote_mint) == is_quote);
+
+        let balance = candidates
+            .next()
+            .ok_or(MonitorError::TokenInfoNotFound(side))?;
+
+        if let Some(other) = candidates.find(|other| other.mint != balance.mint) {
+            return Err(MonitorError::AmbiguousTokenInfo {
+                side,
+                first: balance.mint.clone(),
+                second: other.mint.clone(),
+            });
+        }
+
+        Self::token_info_from_balance(balance)
+    }
+
+    /// Every balance as a `TokenInfo`, sorted by mint then amount. Entries
+    /// without a parseable amount are logged and left out, since they are
+    /// only diagnostics and shouldn't cost the pool.
+    fn all_balances(
+        signature: &Signature,
+        balances: &[UiTransactionTokenBalance],
+    ) -> Vec<TokenInfo> {
+        let mut infos: Vec<TokenInfo> = balances
+            .iter()
+            .filter_map(|balance| match Self::token_info_from_balance(balance) {
+                Ok(info) => Some(info),
+                Err(err) => {
+                    warn!(
+                        "Leaving a balance out of all_balances for {}: {}",
+                        signature, err
+                    );
+                    None
+                }
+            })
+            .collect();
+        // Providers don't agree on balance order; sort so output is stable.
+        infos.sort_by(|a, b| (&a.address, a.amount).cmp(&(&b.address, b.amount)));
+        infos
+    }
+
+    fn token_info_from_balance(
+        balance: &UiTransactionTokenBalance,
+    ) -> Result<TokenInfo, MonitorError> {
+        let amount = balance
+            .ui_token_amount
+            .amount
+            .parse()
+            .map_err(|_| MonitorError::InvalidAmount(balance.mint.clone()))?;
+
+        Ok(TokenInfo::new(
+            balance.mint.clone(),
+            balance.ui_token_amount.decimals,
+            amount,
+        ))
+    }
+
+    /// Derives the quote side from the lamports deposited into the pool's WSOL
+    /// vault, for pools funded with native SOL whose vault has no token
+    /// balance entry. The vault is found through the pool-creation
+    /// instruction's accounts; its rent-exempt reserve isn't liquidity.
+    fn extract_native_quote_info(
+        variant: PoolVariant,
+        account_keys: &[String],
+        instructions: &[UiCompiledInstruction],
+        pre_balances: &[u64],
+        post_balances: &[u64],
+    ) -> Result<TokenInfo, MonitorError> {
+        let account = |index| pool_creation_account(variant, account_keys, instructions, index);
+        let rent_exempt = Rent::default().minimum_balance(spl_token::state::Account::LEN);
+
+        variant
+            .vault_account_indexes()
+            .into_iter()
+            .filter(|(mint, _)| account(*mint).as_deref() == Some(WSOL_MINT))
+            .filter_map(|(_, vault)| {
+                let vault = account(vault)?;
+                let index = account_keys.iter().position(|key| *key == vault)?;
+                let pre = pre_balances.get(index).copied().unwrap_or_default();
+                let post = post_balances.get(index).copied().unwrap_or_default();
+                Some(post.saturating_sub(pre.max(rent_exempt)))
+            })
+            .find(|lamports| *lamports > 0)
+            .map(|lamports| TokenInfo::new(WSOL_MINT.to_string(), SOL_DECIMALS, lamports))
+            .ok_or(MonitorError::TokenInfoNotFound("quote"))
+    }
+
+    /// Prints to stdout, or to stderr when stdout is reserved for records.
+    fn print_status(&self, message: ColoredString) {
+        if self.config.tee_stdout.is_some() {
+            eprintln!("{}", message);
+        } else {
+            println!("{}", message);
+        }
+    }
+
+    async fn store_data(&self, data: &TokenData) -> Result<()> {
+        let date = detection_date(data, self.clock.now());
+        match self.config.output_schema {
+            OutputSchema::Standard => self.store_record(data, date).await,
+            OutputSchema::Legacy => self.store_record(&LegacyTokenData::from(data), date).await,
+        }
+    }
+
+    /// Writes `record` to every store. A failing store is logged and doesn't
+    /// stop the others; only if all of them fail is the record lost and an
+    /// error returned. With `tee_stdout` the record also goes to stdout, in the
+    /// order and with the guarantees described on `TeeOrder`.
+    async fn store_record<T: Serialize>(&self, record: &T, date: NaiveDate) -> Result<()> {
+        // One compact record per line so files stay valid JSON-lines.
+        let json = serde_json::to_string(record)?;
+
+        if self.config.tee_stdout == Some(TeeOrder::StdoutFirst) {
+            print_record(&mut **self.record_output.lock().unwrap(), &json)?;
+        }
+
+        let mut failures = 0;
+        let mut last_error = None;
+        for store in &self.stores {
+            if let Err(err) = store.store(&json, date).await {
+                error!("Error writing to {}: {}", store.name(), err);
+                failures += 1;
+                last_error = Some(err);
+            }
+        }
+
+        match (last_error, self.config.tee_stdout) {
+            (Some(err), Some(_)) => Err(err),
+            (Some(err), None) if failures == self.stores.len() => Err(err),
+            (_, Some(TeeOrder::FileFirst)) => {
+                print_record(&mut **self.record_output.lock().unwrap(), &json)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    async fn log_error(&self, error: &anyhow::Error) -> Result<()> {
+        let error_message = format!(
+            "Error occurred: {}\nTimestamp: {}\n",
+            error,
+            self.clock.now().to_rfc3339()
+        );
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.config.error_log_path)
+            .await?;
+
+        file.write_all(error_message.as_bytes()).await?;
+        file.flush().await?;
+        Ok(())
+    }
+
+    /// Watches the fee address with the configured `source` until `cancel` fires.
+    pub async fn monitor_new_tokens(self: Arc<Self>, cancel: CancellationToken) -> Result<()> {
+        self.print_status("Monitoring new solana tokens...".green());
+
+        let ray_fee_pubkey = Pubkey::from_str(&self.config.ray_fee)?;
+
+        let source = async {
+            match self.config.source {
+                SourceKind::Pubsub => self.run_pubsub(&ray_fee_pubkey, &cancel).await,
+                SourceKind::Poll => self.run_poll(&ray_fee_pubkey, &cancel).await,
+                SourceKind::Replay => self.run_replay(&cancel).await,
+            }
+        };
+
+        match self.config.stats_interval {
+            // The printer never finishes, so this returns when the source does.
+            Some(interval) => tokio::select! {
+                result = source => result,
+                _ = self.print_stats(interval) => Ok(()),
+            },
+            None => source.await,
+        }
+    }
+
+    /// Every `interval`, logs pool and error rates for the last interval and
+    /// averaged over the last `STATS_WINDOW` intervals, the mean enrichment
+    /// time, and how many notifications are waiting in the queue.
+    async fn print_stats(&self, interval: Duration) {
+        let mut window = VecDeque::with_capacity(STATS_WINDOW);
+        let mut ticker = tokio::time::interval(interval);
+        // The first tick completes immediately.
+        ticker.tick().await;
+
+        loop {
+            ticker.tick().await;
+            let sample = self.stats.take_sample();
+            if window.len() == STATS_WINDOW {
+                window.pop_front();
+            }
+            window.push_back(sample);
+
+            let RollingStats {
+                pools_per_sec,
+                errors_per_sec,
+                avg_enrichment_ms,
+            } = RollingStats::over(&window, interval);
+            info!(
+                "{:.2} pools/s ({:.2} avg), {:.2} errors/s ({:.2} avg), {:.1} ms/enrichment, {} queued",
+                sample.pools as f64 / interval.as_secs_f64(),
+                pools_per_sec,
+                sample.errors as f64 / interval.as_secs_f64(),
+                errors_per_sec,
+                avg_enrichment_ms,
+                self.stats.queued.load(Ordering::Relaxed)
+            );
+        }
+    }
+
+    /// Feeds every fixture in `replay_dir` through the pipeline, in file-name
+    /// order, then returns.
+    async fn run_replay(&self, cancel: &CancellationToken) -> Result<()> {
+        let replay_dir = self
+            .config
+            .replay_dir
+            .as_ref()
+            .ok_or_else(|| anyhow!("The replay source needs replay_dir"))?;
+
+        let mut signatures = Vec::new();
+        let mut entries = tokio::fs::read_dir(replay_dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                continue;
+            }
+            if let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) {
+                signatures.push(Signature::from_str(stem)?);
+            }
+        }
+        signatures.sort_by_key(|signature| signature.to_string());
+
+        for signature in signatures {
+            if cancel.is_cancelled() {
+                break;
+            }
+            if let Err(err) = self.handle_signature(&signature).await {
+                error!("Error replaying {}: {}", signature, err);
+                self.log_error(&err).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Streams log notifications, reconnecting both when the server closes the
+    /// subscription and when connecting or subscribing fails; gives up with
+    /// `ReconnectsExhausted` after `max_reconnects` reconnects, if set. After a
+    /// reconnect, signatures that landed while the socket was down are
+    /// backfilled over RPC before live streaming resumes. On cancellation no new
+    /// notifications are pulled, and the ones already queued get up to
+    /// `drain_timeout` to be parsed and stored before returning.
+    async fn run_pubsub(
+        self: &Arc<Self>,
+        address: &Pubkey,
+        cancel: &CancellationToken,
+    ) -> Result<()> {
+        let mut last_seen_slot = None;
+        let mut reconnects = 0;
+
+        loop {
+            let end = match PubsubClient::new(&self.ws_url).await {
+                Ok(pubsub_client) => {
+                    self.stream_logs(&pubsub_client, address, cancel, &mut last_seen_slot)
+                        .await
+                }
+                Err(err) => Err(err.into()),
+            };
+
+            match end {
+                Ok(StreamEnd::Cancelled) => return Ok(()),
+                Ok(StreamEnd::Closed) => warn!("Log subscription closed by the server"),
+                Err(err) => {
+                    error!("Log subscription failed: {}", err);
+                    self.log_error(&err).await?;
+                }
+            }
+
+            reconnects += 1;
+            if let Some(max_reconnects) = self.config.max_reconnects {
+                if reconnects > max_reconnects {
+                    return Err(MonitorError::ReconnectsExhausted(max_reconnects).into());
+                }
+            }
+
+            info!(
+                "Reconnecting in {:?} (reconnect {})",
+                RECONNECT_DELAY, reconnects
+            );
+            self.retry_budget.acquire()?;
+            tokio::select! {
+                _ = cancel.cancelled() => return Ok(()),
+                _ = sleep(RECONNECT_DELAY) => {}
+            }
+        }
+    }
+
+    /// HTTP-only source: polls `getSignaturesForAddress` every `poll_interval` and
+    /// feeds new signatures, oldest first, through the same pipeline as pubsub.
+    /// Polling starts from the newest signature at startup, like a live stream,
+    /// and each poll pages back to the previous newest signature so a burst of
+    /// more than one page between polls isn't cut short. On cancellation the
+    /// signature in flight gets `drain_timeout` to finish and the rest of the
+    /// page is left alone.
+    async fn run_poll(&self, address: &Pubkey, cancel: &CancellationToken) -> Result<()> {
+        let mut until: Option<Signature> = None;
+        let mut primed = false;
+
+        loop {
+            let statuses = if primed {
+                self.signatures_back_to(address, until, None, usize::MAX)
+                    .await
+            } else {
+                let config = GetConfirmedSignaturesForAddress2Config {
+                    before: None,
+                    until,
+                    limit: None,
+                    commitment: Some(self.config.backfill_commitment),
+                };
+                self.rpc_client
+                    .get_signatures_for_address_with_config(address, config)
+                    .await
+            };
+
+            match statuses {
+                Ok(statuses) => {
+                    if let Some(newest) = statuses.first() {
+                        until = Some(Signature::from_str(&newest.signature)?);
+                    }
+
+                    // The first page is history, not new pools.
+                    if primed {
+                        for status in statuses.into_iter().rev() {
+                            if status.err.is_some() {
+                                continue;
+                            }
+                            let signature = Signature::from_str(&status.signature)?;
+
+                            let handling = self.handle_signature(&signature);
+                            tokio::pin!(handling);
+                            let result = tokio::select! {
+                                result = &mut handling => result,
+                                _ = cancel.cancelled() => {
+                                    match timeout(self.config.drain_timeout, &mut handling).await {
+                                        Ok(result) => result,
+                                        Err(_) => return Ok(()),
+                                    }
+                                }
+                            };
+
+                            if let Err(err) = result {
+                                error!("Error processing {}: {}", signature, err);
+                                self.log_error(&err).await?;
+                            }
+                            // The in-flight signature is done; leave the rest of the page.
+                            if cancel.is_cancelled() {
+                                return Ok(());
+                            }
+                        }
+                    }
+                    primed = true;
+                }
+                Err(err) => {
+                    error!("Error getting signatures: {}", err);
+                    self.log_error(&err).await?;
+                    self.retry_budget.acquire()?;
+                }
+            }
+
+            tokio::select! {
+                _ = cancel.cancelled() => return Ok(()),
+                _ = sleep(self.config.poll_interval) => {}
+            }
+        }
+    }
+
+    /// Runs one subscription. Notifications are moved off the websocket channel
+    /// into a bounded queue and parsed by the `workers` tasks, so slow RPC calls
+    /// don't stall the receive side until the queue fills up.
+    ///
+    /// A full queue blocks the reader rather than dropping notifications, so
+    /// during a burst at most `workers` pipelines run and `queue_capacity` wait;
+    /// the rest stay in the pubsub client until there is room.
+    async fn stream_logs(
+        self: &Arc<Self>,
+        pubsub_client: &PubsubClient,
+        address: &Pubkey,
+        cancel: &CancellationToken,
+        last_seen_slot: &mut Option<u64>,
+    ) -> Result<StreamEnd> {
+        let (mut notification_receiver, _subscription) = pubsub_client
+            .logs_subscribe(
+                RpcTransactionLogsFilter::Mentions(vec![address.to_string()]),
+                RpcTransactionLogsConfig {
+                    commitment: Some(self.config.subscribe_commitment),
+                },
+            )
+            .await?;
+
+        // Subscribe first so nothing slips between the backfill and the stream.
+        if let Some(since_slot) = *last_seen_slot {
+            if let Some(newest_slot) = self.backfill(address, since_slot).await? {
+                *last_seen_slot = Some(newest_slot);
+            }
+        }
+
+        let (queue_sender, queue_receiver) = mpsc::channel(self.config.queue_capacity);
+
+        let reader = async {
+            // Owned here so the queue closes once the reader stops.
+            let queue_sender = queue_sender;
+            loop {
+                let logs = tokio::select! {
+                    _ = cancel.cancelled() => {
+                        info!("Cancellation requested, stopping monitor");
+                        return StreamEnd::Cancelled;
+                    }
+                    logs = notification_receiver.next() => match logs {
+                        // Kept as JSON, the shape `notification_signature` reads.
+                        Some(logs) => serde_json::to_value(logs).unwrap_or(Value::Null),
+                        None => return StreamEnd::Closed,
+                    },
+                };
+
+                if let Some(slot) = notification_slot(&logs) {
+                    *last_seen_slot = Some(slot);
+                }
+
+                // Counted first so a worker never takes it off before it's added.
+                self.stats.queued.fetch_add(1, Ordering::Relaxed);
+                tokio::select! {
+                    _ = cancel.cancelled() => {
+                        self.stats.queued.fetch_sub(1, Ordering::Relaxed);
+                        info!("Cancellation requested, stopping monitor");
+                        return StreamEnd::Cancelled;
+                    }
+                    sent = queue_sender.send(logs) => {
+                        // Only fails once the workers are gone, and then nothing is queued.
+                        if sent.is_err() {
+                            self.stats.queued.fetch_sub(1, Ordering::Relaxed);
+                        }
+                    }
+                }
+            }
+        };
+
+        // Works through the queue until the reader closes it. After cancellation
+        // whatever is already queued gets `drain_timeout` to be parsed and stored.
+        let processor = async {
+            let drain_deadline = async {
+                cancel.cancelled().await;
+                sleep(self.config.drain_timeout).await;
+            };
+
+            let monitor = Arc::clone(self);
+            let address = *address;
+            let mut workers = spawn_workers(self.config.workers, queue_receiver, move |logs| {
+                let monitor = Arc::clone(&monitor);
+                async move {
+                    monitor.stats.queued.fetch_sub(1, Ordering::Relaxed);
+                    monitor.process_notification(logs, &address).await
+                }
+            });
+            let drain = async { while workers.join_next().await.is_some() {} };
+
+            // Dropping the set when the deadline passes aborts the workers.
+            tokio::select! {
+                _ = drain => {}
+                _ = drain_deadline => warn!(
+                    "Queued notifications not finished after {:?}, dropping them",
+                    self.config.drain_timeout
+                ),
+            }
+        };
+
+        let (end, ()) = tokio::join!(reader, processor);
+        Ok(end)
+    }
+
+    /// Processes, oldest first, the signatures for `address` in slots after
+    /// `since_slot`. Returns the newest slot that was backfilled, if any.
+    async fn backfill(&self, address: &Pubkey, since_slot: u64) -> Result<Option<u64>> {
+        let missed = self
+            .signatures_back_to(address, None, Some(since_slot), BACKFILL_MAX_SIGNATURES)
+            .await?;
+        if missed.len() == BACKFILL_MAX_SIGNATURES {
+            warn!(
+                "Backfill stopped at {} signatures; older ones since slot {} are skipped",
+                BACKFILL_MAX_SIGNATURES, since_slot
+            );
+        }
+
+        if missed.is_empty() {
+            return Ok(None);
+        }
+
+        info!(
+            "Backfilling {} signatures missed since slot {}",
+            missed.len(),
+            since_slot
+        );
+        let newest_slot = missed.first().map(|status| status.slot);
+
+        for status in missed.into_iter().rev() {
+            if status.err.is_some() {
+                continue;
+            }
+
+            let signature = Signature::from_str(&status.signature)?;
+            if let Err(err) = self.handle_signature(&signature).await {
+                error!("Error backfilling {}: {}", signature, err);
+                self.log_error(&err).await?;
+            }
+        }
+
+        Ok(newest_slot)
+    }
+
+    /// Signatures for `address`, newest first, back to `until` (exclusive) or
+    /// the first slot at or below `since_slot`, at most `max` of them. One
+    /// response holds a single page, so older pages are fetched with `before`.
+    async fn signatures_back_to(
+        &self,
+        address: &Pubkey,
+        until: Option<Signature>,
+        since_slot: Option<u64>,
+        max: usize,
+    ) -> Result<SignaturesPage> {
+        let mut signatures = Vec::new();
+        let mut before = None;
+
+        loop {
+            let page = self
+                .rpc_client
+                .get_signatures_for_address_with_config(
+                    address,
+                    GetConfirmedSignaturesForAddress2Config {
+                        before,
+                        until,
+                        limit: None,
+                        commitment: Some(self.config.backfill_commitment),
+                    },
+                )
+                .await?;
+            // A short page is the last one before `until` or the oldest.
+            let last_page = page.len() < SIGNATURES_PAGE_LIMIT;
+
+            for status in page {
+                if since_slot.is_some_and(|slot| status.slot <= slot) || signatures.len() >= max {
+                    return Ok(signatures);
+                }
+                before = Some(Signature::from_str(&status.signature)?);
+                signatures.push(status);
+            }
+            if last_page {
+                return Ok(signatures);
+            }
+        }
+    }
+
+    /// Starts `on_detect_command` in the background with the record's JSON on
+    /// stdin. The command is killed after `on_detect_timeout`; its exit status
+    /// is only logged.
+    fn run_on_detect_command(&self, token_data: &TokenData) -> Result<()> {
+        let Some(command) = self.config.on_detect_command.clone() else {
+            return Ok(());
+        };
+        let json = serde_json::to_vec(token_data)?;
+        let limit = self.config.on_detect_timeout;
+
+        tokio::spawn(async move {
+            let run = async {
+                let mut child = tokio::process::Command::new("sh")
+                    .arg("-c")
+                    .arg(&command)
+                    .stdin(std::process::Stdio::piped())
+                    .kill_on_drop(true)
+                    .spawn()?;
+                // Dropping stdin after the write closes it, so the command sees EOF.
+                if let Some(mut stdin) = child.stdin.take() {
+                    stdin.write_all(&json).await?;
+                }
+                Ok::<_, anyhow::Error>(child.wait().await?)
+            };
+
+            match timeout(limit, run).await {
+                Ok(Ok(status)) if status.success() => {
+                    info!("on_detect_command exited with {}", status)
+                }
+                Ok(Ok(status)) => warn!("on_detect_command exited with {}", status),
+                Ok(Err(err)) => error!("on_detect_command failed: {}", err),
+                Err(_) => warn!("on_detect_command timed out after {:?}, killed it", limit),
+            }
+        });
+
+        Ok(())
+    }
+
+    /// `handle_log_notification`, reporting failures instead of returning them.
+    async fn process_notification(&self, logs: Value, address: &Pubkey) {
+        if let Err(err) = self.handle_log_notification(logs, address).await {
+            error!("Error processing log: {}", err);
+            if let Err(log_err) = self.log_error(&err).await {
+                error!("Error writing error log: {}", log_err);
+            }
+        }
+    }
+
+    /// Handles one `logsSubscribe` notification. If no signature can be found in
+    /// it, the slot it came from is re-read with `getSignaturesForAddress` so
+    /// the event isn't lost.
+    async fn handle_log_notification(&self, logs: Value, address: &Pubkey) -> Result<()> {
+        if let Some(signature) = notification_signature(&logs) {
+            return self
+                .handle_signature(&Signature::from_str(signature)?)
+                .await;
+        }
+
+        let slot = notification_slot(&logs)
+            .ok_or_else(|| anyhow!("No signature or slot in logs: {}", logs))?;
+        warn!(
+            "No signature in logs for slot {}, polling for it instead",
+            slot
+        );
+        self.backfill(address, slot.saturating_sub(1)).await?;
+        Ok(())
+    }
+
+    async fn handle_signature(&self, signature: &Signature) -> Result<()> {
+        if self
+            .handled_signatures
+            .lock()
+            .unwrap()
+            .put(*signature, ())
+            .is_some()
+        {
+            self.emit(DetectionEvent::Filtered {
+                signature: signature.to_string(),
+                reason: "duplicate signature notification".to_string(),
+            })
+            .await;
+            return Ok(());
+        }
+
+        self.print_status(format!("Found new token signature: {}", signature).on_green());
+
+        let result = match self.config.max_rpc_calls_per_event {
+            Some(cap) => {
+                EVENT_RPC_CALLS
+                    .scope(Cell::new((cap, cap)), self.process_signature(signature))
+                    .await
+            }
+            None => self.process_signature(signature).await,
+        };
+        match result {
+            Ok(event) => {
+                if matches!(
+                    event,
+                    DetectionEvent::Created { .. } | DetectionEvent::Minimal(_)
+                ) {
+                    self.stats.pools.fetch_add(1, Ordering::Relaxed);
+                }
+                self.emit(event).await;
+                Ok(())
+            }
+            Err(err) => {
+                self.stats.errors.fetch_add(1, Ordering::Relaxed);
+                #[cfg(feature = "sentry")]
+                report_error(&err, signature);
+                // Let a later notification or backfill try it again.
+                self.handled_signatures.lock().unwrap().pop(signature);
+                self.emit(DetectionEvent::Failed {
+                    signature: signature.to_string(),
+                    error: err.to_string(),
+                })
+                .await;
+                Err(err)
+            }
+        }
+    }
+
+    async fn process_signature(&self, signature: &Signature) -> Result<DetectionEvent> {
+        // Decided before fetching so unsampled signatures cost no RPC calls.
+        if !is_sampled(signature, self.config.sample_rate) {
+            return Ok(DetectionEvent::Filtered {
+                signature: signature.to_string(),
+                reason: format!("not in the {} sample", self.config.sample_rate),
+            });
+        }
+
+        match self.parse_transaction(signature).await? {
+            ParseOutcome::Pool(token_data) => {
+                let dedup_key = self.dedup_key(
+                    &token_data.lp_signature,
+                    &token_data.base_info.address,
+                    &token_data.pool_address,
+                );
+                let result = self.finish_pool(signature, token_data).await;
+                if result.is_err() {
+                    // Not stored, so a retry must not be filtered as a duplicate.
+                    self.seen.lock().unwrap().pop(&dedup_key);
+                }
+                result
+            }
+            ParseOutcome::Skipped(reason) => Ok(DetectionEvent::Filtered {
+                signature: signature.to_string(),
+                reason,
+            }),
+        }
+    }
+
+    /// Rewrites the records in `input` to `output` in the current schema.
+    /// Records whose transaction was saved by `record_dir` are parsed again
+    /// from `replay_dir`, keeping their original `timestamp`; the rest, and
+    /// any that no longer parse as a pool, pass through with new fields
+    /// defaulted.
+    pub async fn reparse(&self, input: &Path, output: &Path) -> Result<ReparseReport> {
+        let replay_dir =
+            self.config.replay_dir.as_deref().ok_or_else(|| {
+                anyhow!("reparse needs replay_dir set to the recorded transactions")
+            })?;
+        let mut lines = BufReader::new(File::open(input).await?).lines();
+        let mut writer = BufWriter::new(File::create(output).await?);
+        let mut report = ReparseReport::default();
+
+        while let Some(line) = lines.next_line().await? {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let stored = read_token_data(&line, SchemaMode::Lenient)?;
+            let signature = Signature::from_str(&stored.lp_signature)?;
+
+            let mut data = stored;
+            if tokio::fs::try_exists(fixture_path(replay_dir, &signature)).await? {
+                match self.parse_transaction(&signature).await? {
+                    ParseOutcome::Pool(mut reparsed) => {
+                        reparsed.timestamp = data.timestamp;
+                        data = *reparsed;
+                        report.reparsed += 1;
+                    }
+                    ParseOutcome::Skipped(reason) => {
+                        warn!("Keeping {} as stored: {}", signature, reason);
+                        report.passed_through += 1;
+                    }
+                }
+            } else {
+                report.passed_through += 1;
+            }
+
+            let mut line = serde_json::to_string(&data)?;
+            line.push('\n');
+            writer.write_all(line.as_bytes()).await?;
+        }
+
+        writer.flush().await?;
+        Ok(report)
+    }
+
+    /// What a detection is deduplicated on under `dedup_key`.
+    fn dedup_key(&self, signature: &str, base_mint: &str, pool_address: &str) -> String {
+        match self.config.dedup_key {
+            DedupKey::Signature => signature,
+            DedupKey::BaseMint => base_mint,
+            DedupKey::PoolAccount => pool_address,
+        }
+        .to_string()
+    }
+
+    /// Waits for confirmations, stores the pool and runs the per-pool hooks.
+    async fn finish_pool(
+        &self,
+        signature: &Signature,
+        token_data: Box<TokenData>,
+    ) -> Result<DetectionEvent> {
+        if self.config.min_confirmations > 0 {
+            self.wait_for_confirmations(signature).await?;
+        }
+
+        if self.config.minimal {
+            let minimal = MinimalEvent {
+                signature: token_data.lp_signature,
+                slot: token_data.slot,
+                base_mint: token_data.base_info.address,
+            };
+            self.store_record(&minimal, self.clock.now().date_naive())
+                .await?;
+            return Ok(DetectionEvent::Minimal(minimal));
+        }
+
+        self.store_data(&token_data).await?;
+        self.run_on_detect_command(&token_data)?;
+        if let Some(strategy) = &self.strategy {
+            let decision = strategy.on_pool(&token_data);
+            info!("Strategy decision for {}: {:?}", signature, decision);
+            self.emit(DetectionEvent::Decision {
+                signature: signature.to_string(),
+                decision,
+            })
+            .await;
+        }
+        let detection_latency_ms = match token_data.block_time {
+            Some(block_time) if !token_data.block_time_estimated => {
+                Some(self.clock.now().timestamp_millis() - block_time * 1_000)
+            }
+            _ => None,
+        };
+        Ok(DetectionEvent::Created {
+            token_data,
+            detection_latency_ms,
+        })
+    }
+
+    async fn emit(&self, event: DetectionEvent) {
+        if let Some(event_log) = &self.event_log {
+            if let Err(err) = event_log.append(&event).await {
+                error!("Error writing event log: {}", err);
+            }
+        }
+        if let Some(events) = &self.events {
+            // A closed channel only means nobody is listening any more.
+            let _ = events.send(event).await;
+        }
+    }
+}
+
+/// Account keys and top-level instructions of `transaction`, in the
+/// index-based form the detection code reads. A `jsonParsed` message is mapped
+/// back to that form; instructions the RPC fully parsed no longer carry their
+/// data and are left out.
+fn message_parts(
+    transaction: EncodedTransaction,
+) -> Result<(Vec<String>, Vec<UiCompiledInstruction>)> {
+    let EncodedTransaction::Json(UiTransaction { message, .. }) = transaction else {
+        return Err(anyhow!("Transaction was not returned as JSON"));
+    };
+
+    match message {
+        UiMessage::Raw(raw) => Ok((raw.account_keys, raw.instructions)),
+        UiMessage::Parsed(parsed) => {
+            let account_keys: Vec<String> = parsed
+                .account_keys
+                .into_iter()
+                .map(|account| account.pubkey)
+                .collect();
+            let index_of = |key: &str| {
+                let index = account_keys.iter().position(|account| account == key)?;
+                u8::try_from(index).ok()
+            };
+
+            let instructions = parsed
+                .instructions
+                .into_iter()
+                .filter_map(|instruction| match instruction {
+                    UiInstruction::Compiled(compiled) => Some(compiled),
+                    UiInstruction::Parsed(UiParsedInstruction::PartiallyDecoded(decoded)) => {
+                        Some(UiCompiledInstruction {
+                            program_id_index: index_of(&decoded.program_id)?,
+                            accounts: decoded
+                                .accounts
+                                .iter()
+                                .map(|account| index_of(account))
+                                .collect::<Option<_>>()?,
+                            data: decoded.data,
+                            stack_height: decoded.stack_height,
+                        })
+                    }
+                    UiInstruction::Parsed(UiParsedInstruction::Parsed(_)) => None,
+                })
+                .collect();
+            Ok((account_keys, instructions))
+        }
+    }
+}
+
+fn fixture_path(dir: &Path, signature: &Signature) -> PathBuf {
+    dir.join(format!("{}.json", signature))
+}
+
+/// Owner of a token balance. RPC nodes older than 1.11 leave it out (`Skip`)
+/// and it can be explicitly `None`; neither matches any authority.
+fn balance_owner(balance: &UiTransactionTokenBalance) -> Option<&str> {
+    match &balance.owner {
+        OptionSerializer::Some(owner) => Some(owner.as_str()),
+        OptionSerializer::None | OptionSerializer::Skip => None,
+    }
+}
+
+/// Where the notification value sits in the payload shapes providers send: the
+/// bare value, wrapped in `value`/`result`, or the whole JSON-RPC message.
+fn notification_roots(logs: &Value) -> [&Value; 6] {
+    [
+        logs,
+        &logs["value"],
+        &logs["result"],
+        &logs["result"]["value"],
+        &logs["params"]["result"],
+        &logs["params"]["result"]["value"],
+    ]
+}
+
+fn notification_signature(logs: &Value) -> Option<&str> {
+    notification_roots(logs)
+        .into_iter()
+        .find_map(|root| root["signature"].as_str())
+}
+
+fn notification_slot(logs: &Value) -> Option<u64> {
+    notification_roots(logs)
+        .into_iter()
+        .find_map(|root| root["context"]["slot"].as_u64())
+}
+
+/// Spawns `count` tasks that take turns pulling from `receiver` and run `work`
+/// on each item, so at most `count` items are worked on at once. The tasks end
+/// once the channel is closed and empty.
+fn spawn_workers<T, F, Fut>(count: usize, receiver: mpsc::Receiver<T>, work: F) -> JoinSet<()>
+where
+    T: Send + 'static,
+    F: Fn(T) -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = ()> + Send,
+{
+    let receiver = Arc::new(tokio::sync::Mutex::new(receiver));
+    let mut workers = JoinSet::new();
+    for _ in 0..count {
+        let receiver = Arc::clone(&receiver);
+        let work = work.clone();
+        workers.spawn(async move {
+            loop {
+                // Only held while waiting, not while working.
+                let item = receiver.lock().await.recv().await;
+                match item {
+                    Some(item) => work(item).await,
+                    None => break,
+                }
+            }
+        });
+    }
+    workers
+}
+
+/// Sends a failed signature to Sentry, tagged with the signature. Skips are
+/// `Filtered` events, not errors, so they never get here.
+#[cfg(feature = "sentry")]
+fn report_error(err: &anyhow::Error, signature: &Signature) {
+    sentry::with_scope(
+        |scope| scope.set_tag("signature", signature),
+        || sentry::integrations::anyhow::capture_anyhow(err),
+    );
+}
+
+/// The first of `required` that is neither a static account key nor loaded
+/// from an address lookup table, or `None` if the transaction mentions them all.
+fn missing_mention<'a>(
+    required: &'a [String],
+    account_keys: &[String],
+    loaded_addresses: &OptionSerializer<UiLoadedAddresses>,
+) -> Option<&'a str> {
+    let is_loaded = |address: &String| match loaded_addresses {
+        OptionSerializer::Some(loaded) => {
+            loaded.writable.contains(address) || loaded.readonly.contains(address)
+        }
+        OptionSerializer::None | OptionSerializer::Skip => false,
+    };
+    required
+        .iter()
+        .find(|address| !account_keys.contains(address) && !is_loaded(address))
+        .map(String::as_str)
+}
+
+/// Account at `index` in the pool-creation instruction's accounts, if the
+/// transaction contains one for `variant`.
+fn pool_creation_account(
+    variant: PoolVariant,
+    account_keys: &[String],
+    instructions: &[UiCompiledInstruction],
+    index: usize,
+) -> Option<String> {
+    instructions
+        .iter()
+        .filter(|instruction| {
+            account_keys
+                .get(instruction.program_id_index as usize)
+                .map(String::as_str)
+                == Some(variant.program_id())
+        })
+        .find(|instruction| {
+            bs58::decode(&instruction.data)
+                .into_vec()
+                .is_ok_and(|data| variant.is_pool_creation(&data))
+        })
+        .and_then(|instruction| instruction.accounts.get(index))
+        .and_then(|index| account_keys.get(*index as usize))
+        .cloned()
+}
+
+/// Whether an SPL Token `InitializeMint`/`InitializeMint2` for `mint` is among
+/// the transaction's top-level instructions.
+fn initializes_mint(
+    account_keys: &[String],
+    instructions: &[UiCompiledInstruction],
+    mint: &str,
+) -> bool {
+    let token_program = spl_token::id().to_string();
+
+    instructions
+        .iter()
+        .filter(|instruction| {
+            account_keys.get(instruction.program_id_index as usize) == Some(&token_program)
+        })
+        .filter(|instruction| {
+            instruction
+                .accounts
+                .first()
+                .and_then(|index| account_keys.get(*index as usize))
+                .map(String::as_str)
+                == Some(mint)
+        })
+        .any(|instruction| {
+            // `TokenInstruction` borrows the bytes it was unpacked from, so it is
+            // matched before `data` goes out of scope.
+            bs58::decode(&instruction.data)
+                .into_vec()
+                .is_ok_and(|data| {
+                    matches!(
+                        TokenInstruction::unpack(&data),
+                        Ok(TokenInstruction::InitializeMint { .. }
+                            | TokenInstruction::InitializeMint2 { .. })
+                    )
+                })
+        })
+}
+
+/// UTC day a detection belongs to. Falls back to `now` if the stored timestamp
+/// can't be parsed.
+fn detection_date(data: &TokenData, now: DateTime<Utc>) -> NaiveDate {
+    parse_timestamp(&data.timestamp)
+        .map(|timestamp| timestamp.date_naive())
+        .unwrap_or_else(|| now.date_naive())
+}
+
+/// What `TokenMonitor::reparse` did with the records it read.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ReparseReport {
+    /// Parsed again from their recorded transaction.
+    pub reparsed: usize,
+    /// Written back from the stored record alone.
+    pub passed_through: usize,
+}
+
+pub async fn run_token_monitor(
+    rpc_url: &str,
+    ws_url: &str,
+    data_path: PathBuf,
+    config: MonitorConfig,
+    cancel: CancellationToken,
+) -> Result<()> {
+    let monitor = TokenMonitor::new(rpc_url, ws_url, data_path, config).await?;
+    Arc::new(monitor).monitor_new_tokens(cancel).await
+}
+
+/// Length-delimited Protobuf export of `TokenData`, for consumers that don't
+/// want JSON. The schema lives in `proto/token_data.proto`; the messages here
+/// mirror it by hand so the build doesn't need `protoc`.
+#[cfg(feature = "protobuf")]
+mod protobuf {
+    use {
+        super::{TokenData, TokenInfo},
+        anyhow::Result,
+        prost::{bytes::Buf, Message},
+        std::{
+            fs::File,
+            io::{BufWriter, Read, Write},
+            path::Path,
+        },
+    };
+
+    #[derive(Clone, PartialEq, Message)]
+    pub struct TokenInfoMessage {
+        #[prost(string, tag = "1")]
+        pub address: String,
+        #[prost(uint32, tag = "2")]
+        pub decimals: u32,
+        #[prost(uint64, tag = "3")]
+        pub amount: u64,
+        #[prost(string, tag = "4")]
+        pub lp_amount: String,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    pub struct TokenDataMessage {
+        #[prost(string, tag = "1")]
+        pub lp_signature: String,
+        #[prost(string, tag = "2")]
+        pub pool_address: String,
+        #[prost(string, tag = "3")]
+        pub creator: String,
+        #[prost(string, tag = "4")]
+        pub timestamp: String,
+        #[prost(uint64, tag = "5")]
+        pub slot: u64,
+        #[prost(int64, optional, tag = "6")]
+        pub block_time: Option<i64>,
+        #[prost(message, optional, tag = "7")]
+        pub base_info: Option<TokenInfoMessage>,
+        #[prost(message, optional, tag = "8")]
+        pub quote_info: Option<TokenInfoMessage>,
+        #[prost(message, repeated, tag = "9")]
+        pub all_balances: Vec<TokenInfoMessage>,
+        #[prost(bool, optional, tag = "10")]
+        pub mint_verified: Option<bool>,
+        #[prost(bool, tag = "11")]
+        pub enrichment_complete: bool,
+        #[prost(bool, tag = "12")]
+        pub fresh_mint: bool,
+        #[prost(double, optional, tag = "13")]
+        pub top_holder_pct: Option<f64>,
+        #[prost(bool, optional, tag = "14")]
+        pub mint_authority_set: Option<bool>,
+        #[prost(bool, optional, tag = "15")]
+        pub freeze_authority_set: Option<bool>,
+        #[prost(uint32, tag = "16")]
+        pub risk_score: u32,
+        #[prost(string, tag = "17")]
+        pub quote_symbol: String,
+        #[prost(bool, tag = "18")]
+        pub block_time_estimated: bool,
+        #[prost(string, tag = "19")]
+        pub fee_payer: String,
+        #[prost(string, optional, tag = "20")]
+        pub initializer: Option<String>,
+        #[prost(uint64, optional, tag = "21")]
+        pub compute_units: Option<u64>,
+        #[prost(uint64, tag = "22")]
+        pub fee_lamports: u64,
+    }
+
+    impl From<&TokenInfo> for TokenInfoMessage {
+        fn from(info: &TokenInfo) -> Self {
+            Self {
+                address: info.address.clone(),
+                decimals: info.decimals as u32,
+                amount: info.amount,
+                lp_amount: info.lp_amount.to_string(),
+            }
+        }
+    }
+
+    impl TryFrom<TokenInfoMessage> for TokenInfo {
+        type Error = anyhow::Error;
+
+        fn try_from(message: TokenInfoMessage) -> Result<Self> {
+            Ok(Self {
+                address: message.address,
+                decimals: message.decimals.try_into()?,
+                amount: message.amount,
+                lp_amount: message.lp_amount.parse()?,
+            })
+        }
+    }
+
+    impl From<&TokenData> for TokenDataMessage {
+        fn from(data: &TokenData) -> Self {
+            Self {
+                lp_signature: data.lp_signature.clone(),
+                pool_address: data.pool_address.clone(),
+                creator: data.creator.clone(),
+                timestamp: data.timestamp.clone(),
+                slot: data.slot,
+                block_time: data.block_time,
+                base_info: Some((&data.base_info).into()),
+                quote_info: Some((&data.quote_info).into()),
+                all_balances: data.all_balances.iter().map(Into::into).collect(),
+                mint_verified: data.mint_verified,
+                enrichment_complete: data.enrichment_complete,
+                fresh_mint: data.fresh_mint,
+                top_holder_pct: data.top_holder_pct,
+                mint_authority_set: data.mint_authority_set,
+                freeze_authority_set: data.freeze_authority_set,
+                risk_score: data.risk_score as u32,
+                quote_symbol: data.quote_symbol.clone(),
+                block_time_estimated: data.block_time_estimated,
+                fee_payer: data.fee_payer.clone(),
+                initializer: data.initializer.clone(),
+                compute_units: data.compute_units,
+                fee_lamports: data.fee_lamports,
+            }
+        }
+    }
+
+    impl TryFrom<TokenDataMessage> for TokenData {
+        type Error = anyhow::Error;
+
+        fn try_from(message: TokenDataMessage) -> Result<Self> {
+            let side = |info: Option<TokenInfoMessage>, name: &str| {
+                info.ok_or_else(|| anyhow::anyhow!("Message is missing {}", name))
+                    .and_then(TokenInfo::try_from)
+            };
+
+            Ok(Self {
+                lp_signature: message.lp_signature,
+                pool_address: message.pool_address,
+                creator: message.creator,
+                timestamp: message.timestamp,
+                slot: message.slot,
+                block_time: message.block_time,
+                base_info: side(message.base_info, "base_info")?,
+                quote_info: side(message.quote_info, "quote_info")?,
+                all_balances: message
+                    .all_balances
+                    .into_iter()
+                    .map(TokenInfo::try_from)
+                    .collect::<Result<_>>()?,
+                mint_verified: message.mint_verified,
+                enrichment_complete: message.enrichment_complete,
+                fresh_mint: message.fresh_mint,
+                top_holder_pct: message.top_holder_pct,
+                mint_authority_set: message.mint_authority_set,
+                freeze_authority_set: message.freeze_authority_set,
+                risk_score: message.risk_score.try_into()?,
+                quote_symbol: message.quote_symbol,
+                block_time_estimated: message.block_time_estimated,
+                fee_payer: message.fee_payer,
+                initializer: message.initializer,
+                compute_units: message.compute_units,
+                fee_lamports: message.fee_lamports,
+            })
+        }
+    }
+
+    /// Writes `TokenData` records as length-delimited `TokenDataMessage`s.
+    pub struct ProtobufStore {
+        writer: BufWriter<File>,
+    }
+
+    impl ProtobufStore {
+        /// Creates `path`, replacing any previous export.
+        pub fn create(path: &Path) -> Result<Self> {
+            Ok(Self {
+                writer: BufWriter::new(File::create(path)?),
+            })
+        }
+
+        pub fn append(&mut self, data: &TokenData) -> Result<()> {
+            let message = TokenDataMessage::from(data);
+            self.writer
+                .write_all(&message.encode_length_delimited_to_vec())?;
+            Ok(())
+        }
+
+        pub fn flush(&mut self) -> Result<()> {
+            self.writer.flush()?;
+            Ok(())
+        }
+    }
+
+    /// Reads back every record written by `ProtobufStore`.
+    pub fn read_protobuf_records(path: &Path) -> Result<Vec<TokenData>> {
+        let mut bytes = Vec::new();
+        File::open(path)?.read_to_end(&mut bytes)?;
+
+        let mut buf = bytes.as_slice();
+        let mut records = Vec::new();
+        while buf.has_remaining() {
+            let message = TokenDataMessage::decode_length_delimited(&mut buf)?;
+            records.push(message.try_into()?);
+        }
+        Ok(records)
+    }
+}
+
+/// A watched address with no transaction this recent is reported as quiet.
+const DIAGNOSE_MAX_IDLE: Duration = Duration::from_secs(60 * 60);
+
+/// One line of the `diagnose` checklist.
+pub struct DiagnosticCheck {
+    pub name: &'static str,
+    /// What was found, as `Ok` for a pass and `Err` for a failure.
+    pub outcome: Result<String, String>,
+    /// What to try when the check fails.
+    pub hint: &'static str,
+}
+
+/// Checks that `config` can actually run: the RPC and websocket endpoints
+/// answer, the data file can be written, and the watched address is valid
+/// and has recent activity. Every check runs even if an earlier one fails,
+/// once the RPC client itself can be built.
+pub async fn diagnose(config: &AppConfig) -> Vec<DiagnosticCheck> {
+    let rpc_client = match http_rpc_client(&config.rpc_url, &config.monitor) {
+        Ok(rpc_client) => ReadOnlyRpcClient::new(rpc_client),
+        Err(err) => {
+            return vec![DiagnosticCheck {
+                name: "RPC endpoint",
+                outcome: Err(format!("can't build the client: {}", err)),
+                hint: "check RAYDIUM_USER_AGENT and RAYDIUM_SESSION_ID",
+            }]
+        }
+    };
+    diagnose_with(&rpc_client, config).await
+}
+
+/// `diagnose`, asking `rpc_client` instead of `config.rpc_url`.
+async fn diagnose_with(rpc_client: &ReadOnlyRpcClient, config: &AppConfig) -> Vec<DiagnosticCheck> {
+    let mut checks = Vec::new();
+
+    let rpc = async {
+        rpc_client.get_health().await?;
+        let version = rpc_client.get_version().await?;
+        Ok::<_, anyhow::Error>(format!("healthy, solana-core {}", version.solana_core))
+    };
+    checks.push(DiagnosticCheck {
+        name: "RPC endpoint",
+        outcome: rpc.await.map_err(|err| err.to_string()),
+        hint: "check RAYDIUM_RPC_URL and that the provider key is valid",
+    });
+
+    let websocket = match PubsubClient::new(&config.ws_url).await {
+        Ok(client) => {
+            let _ = client.shutdown().await;
+            Ok("connected".to_string())
+        }
+        Err(err) => Err(err.to_string()),
+    };
+    checks.push(DiagnosticCheck {
+        name: "Websocket endpoint",
+        outcome: websocket,
+        hint: "check RAYDIUM_WS_URL, or set RAYDIUM_SOURCE=poll if the provider has none",
+    });
+
+    checks.push(DiagnosticCheck {
+        name: "Output path",
+        outcome: check_writable(&config.data_path)
+            .map(|()| format!("{} is writable", config.data_path.display()))
+            .map_err(|err| format!("{}: {}", config.data_path.display(), err)),
+        hint: "create the directory or point RAYDIUM_DATA_PATH somewhere writable",
+    });
+
+    let activity = async {
+        let address = Pubkey::from_str(&config.monitor.ray_fee).map_err(|err| {
+            anyhow!(
+                "'{}' is not a valid pubkey: {}",
+                config.monitor.ray_fee,
+                err
+            )
+        })?;
+        let latest = rpc_client
+            .get_signatures_for_address_with_config(
+                &address,
+                GetConfirmedSignaturesForAddress2Config {
+                    limit: Some(1),
+                    ..Default::default()
+                },
+            )
+            .await?;
+        let block_time = latest
+            .first()
+            .ok_or_else(|| anyhow!("{} has no transactions", address))?
+            .block_time
+            .ok_or_else(|| anyhow!("the latest transaction of {} has no block time", address))?;
+
+        let idle = Utc::now().timestamp() - block_time;
+        if idle > DIAGNOSE_MAX_IDLE.as_secs() as i64 {
+            return Err(anyhow!("{} has been idle for {}s", address, idle));
+        }
+        Ok(format!("{} was active {}s ago", address, idle))
+    };
+    checks.push(DiagnosticCheck {
+        name: "Watched address",
+        outcome: activity.await.map_err(|err| err.to_string()),
+        hint: "check RAYDIUM_RAY_FEE is the pool-creation fee account for RAYDIUM_VARIANT",
+    });
+
+    checks
+}
+
+/// Whether records can be appended to `path`, without changing it. A file
+/// created only for the check is removed again.
+fn check_writable(path: &Path) -> std::io::Result<()> {
+    let existed = path.exists();
+    std::fs::OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(path)?;
+    if !existed {
+        std::fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// Writes one record line to `output` and flushes it, so a consumer reading
+/// the pipe sees it before anything that follows.
+fn print_record(output: &mut dyn std::io::Write, json: &str) -> Result<()> {
+    writeln!(output, "{}", json)?;
+    output.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::load_records;
+    use solana_client::{
+        client_error::Result as ClientResult,
+        rpc_request::RpcRequest,
+        rpc_sender::{RpcSender, RpcTransportStats},
+    };
+    use solana_sdk::message::MessageHeader;
+    use solana_transaction_status::{
+        option_serializer::OptionSerializer, EncodedConfirmedTransactionWithStatusMeta,
+        EncodedTransactionWithStatusMeta, UiRawMessage, UiTransactionStatusMeta,
+        UiTransactionTokenBalance,
+    };
+    use std::{collections::HashSet, env};
+    use std::{
+        collections::{HashMap, VecDeque},
+        path::Path,
+        sync::{atomic::AtomicUsize, Arc, Mutex},
+    };
+    use tokio::time::timeout;
+
+    /// A pool-creation transaction, built into an RPC response by
+    /// `transaction()`. Tests change the fields they care about.
+    struct PoolFixture {
+        variant: PoolVariant,
+        signature: Signature,
+        fee_payer: String,
+        /// Wallet the pool-creation instruction names as creating the pool.
+        initializer: String,
+        pool: String,
+        base_mint: String,
+        quote_mint: String,
+        /// Pool token accounts holding each side.
+        base_vault: String,
+        quote_vault: String,
+        base_amount: u64,
+        quote_amount: u64,
+        /// Quote the pool in native SOL: the quote vault has no token balance
+        /// and is created holding this many lamports over its rent-exempt
+        /// reserve, paid by the fee payer.
+        native_quote_lamports: Option<u64>,
+        /// Create the base mint in the same transaction.
+        fresh_mint: bool,
+        /// Leave `meta` out of the response, as minimal RPC responses can.
+        with_meta: bool,
+        /// `None` as from a node that doesn't know the block's time.
+        block_time: Option<i64>,
+        compute_units: Option<u64>,
+        fee: u64,
+    }
+
+    impl PoolFixture {
+        fn new() -> Self {
+            Self {
+                variant: PoolVariant::AmmV4,
+                signature: Signature::new_unique(),
+                fee_payer: Pubkey::new_unique().to_string(),
+                initializer: Pubkey::new_unique().to_string(),
+                pool: Pubkey::new_unique().to_string(),
+                base_mint: Pubkey::new_unique().to_string(),
+                quote_mint: WSOL_MINT.to_string(),
+                base_vault: Pubkey::new_unique().to_string(),
+                quote_vault: Pubkey::new_unique().to_string(),
+                base_amount: 1_000_000,
+                quote_amount: 50_000_000_000,
+                native_quote_lamports: None,
+                fresh_mint: false,
+                with_meta: true,
+                block_time: Some(1_700_000_000),
+                compute_units: Some(150_000),
+                fee: 5_000,
+            }
+        }
+
+        fn transaction(&self) -> EncodedConfirmedTransactionWithStatusMeta {
+            // Indexes into `account_keys`.
+            let (fee_payer, initializer, pool, owner, base_mint, quote_mint) =
+                (0_u8, 1, 2, 3, 4, 5);
+            let (program, token_program, base_vault, quote_vault) = (6, 7, 8, 9);
+            let account_keys = vec![
+                self.fee_payer.clone(),
+                self.initializer.clone(),
+                self.pool.clone(),
+                self.variant.authority().to_string(),
+                self.base_mint.clone(),
+                self.quote_mint.clone(),
+                self.variant.program_id().to_string(),
+                spl_token::id().to_string(),
+                self.base_vault.clone(),
+                self.quote_vault.clone(),
+            ];
+
+            // Every account `initialize2` (21) or CPMM `initialize` (20) takes;
+            // the ones not looked at are the authority.
+            let account_count = match self.variant {
+                PoolVariant::AmmV4 => 21,
+                PoolVariant::Cpmm => 20,
+            };
+            let mut accounts = vec![owner; account_count];
+            accounts[self.variant.pool_account_index()] = pool;
+            accounts[self.variant.initializer_account_index()] = initializer;
+            let [(base_mint_index, base_vault_index), (quote_mint_index, quote_vault_index)] =
+                self.variant.vault_account_indexes();
+            accounts[base_mint_index] = base_mint;
+            accounts[base_vault_index] = base_vault;
+            accounts[quote_mint_index] = quote_mint;
+            accounts[quote_vault_index] = quote_vault;
+            let data = match self.variant {
+                PoolVariant::AmmV4 => vec![AMM_V4_INITIALIZE2_TAG, 254, 0, 0],
+                PoolVariant::Cpmm => CPMM_INITIALIZE_DISCRIMINATOR.to_vec(),
+            };
+            let mut instructions = vec![UiCompiledInstruction {
+                program_id_index: program,
+                accounts,
+                data: bs58::encode(data).into_string(),
+                stack_height: None,
+            }];
+            if self.fresh_mint {
+                let mint = Pubkey::from_str(&self.base_mint).unwrap();
+                let payer = Pubkey::from_str(&self.fee_payer).unwrap();
+                let initialize = spl_token::instruction::initialize_mint2(
+                    &spl_token::id(),
+                    &mint,
+                    &payer,
+                    None,
+                    6,
+                )
+                .unwrap();
+                instructions.insert(
+                    0,
+                    UiCompiledInstruction {
+                        program_id_index: token_program,
+                        accounts: vec![base_mint],
+                        data: bs58::encode(initialize.data).into_string(),
+                        stack_height: None,
+                    },
+                );
+            }
+
+            let authority = self.variant.authority();
+            let mut post_token_balances =
+                vec![pool_balance(&self.base_mint, authority, self.base_amount)];
+            // The fee payer funds both new vaults; the authority PDA only signs
+            // for them, so its lamports don't move.
+            let rent = Rent::default().minimum_balance(spl_token::state::Account::LEN);
+            let mut pre_balances = vec![1_461_600; account_keys.len()];
+            pre_balances[usize::from(fee_payer)] = 100_000_000_000;
+            pre_balances[usize::from(base_vault)] = 0;
+            pre_balances[usize::from(quote_vault)] = 0;
+            let mut post_balances = pre_balances.clone();
+            post_balances[usize::from(base_vault)] = rent;
+            post_balances[usize::from(quote_vault)] = rent;
+            let mut deposited = 0;
+            match self.native_quote_lamports {
+                Some(lamports) => {
+                    post_balances[usize::from(quote_vault)] += lamports;
+                    deposited = lamports;
+                }
+                None => post_token_balances.push(pool_balance(
+                    &self.quote_mint,
+                    authority,
+                    self.quote_amount,
+                )),
+            }
+            post_balances[usize::from(fee_payer)] -= self.fee + 2 * rent + deposited;
+
+            let meta = UiTransactionStatusMeta {
+                err: None,
+                status: Ok(()),
+                fee: self.fee,
+                pre_balances,
+                post_balances,
+                inner_instructions: OptionSerializer::None,
+                log_messages: OptionSerializer::None,
+                pre_token_balances: OptionSerializer::None,
+                post_token_balances: OptionSerializer::Some(post_token_balances),
+                rewards: OptionSerializer::None,
+                loaded_addresses: OptionSerializer::Skip,
+                return_data: OptionSerializer::Skip,
+                compute_units_consumed: self.compute_units.into(),
+            };
+
+            EncodedConfirmedTransactionWithStatusMeta {
+                slot: 42,
+                transaction: EncodedTransactionWithStatusMeta {
+                    transaction: EncodedTransaction::Json(UiTransaction {
+                        signatures: vec![self.signature.to_string()],
+                        message: UiMessage::Raw(UiRawMessage {
+                            header: MessageHeader::default(),
+                            account_keys,
+                            recent_blockhash: String::new(),
+                            instructions,
+                            address_table_lookups: None,
+                        }),
+                    }),
+                    meta: self.with_meta.then_some(meta),
+                    version: None,
+                },
+                block_time: self.block_time,
+            }
+        }
+
+        /// An RPC double answering `getTransaction` with this transaction.
+        fn rpc(&self) -> ScriptedRpc {
+            let rpc = ScriptedRpc::default();
+            rpc.reply(
+                RpcRequest::GetTransaction,
+                serde_json::to_value(self.transaction()).unwrap(),
+            );
+            rpc
+        }
+
+        /// Saves the transaction where a `replay_dir` of `dir` finds it.
+        fn write(&self, dir: &Path) -> Signature {
+            std::fs::create_dir_all(dir).unwrap();
+            let json = serde_json::to_vec(&self.transaction()).unwrap();
+            std::fs::write(fixture_path(dir, &self.signature), json).unwrap();
+            self.signature
+        }
+    }
+
+    /// Monitor reading transactions from `dir/fixtures` and writing to
+    /// `dir/pools.json`, with the mock RPC behind it for anything else.
+    async fn replay_monitor(
+        dir: &Path,
+        config: MonitorConfig,
+    ) -> (TokenMonitor, mpsc::Receiver<DetectionEvent>) {
+        replay_monitor_with(dir, &ScriptedRpc::default(), config).await
+    }
+
+    async fn replay_monitor_with(
+        dir: &Path,
+        rpc: &ScriptedRpc,
+        config: MonitorConfig,
+    ) -> (TokenMonitor, mpsc::Receiver<DetectionEvent>) {
+        let config = MonitorConfig {
+            replay_dir: Some(dir.join("fixtures")),
+            ..config
+        };
+        let mut monitor = mock_monitor(dir, rpc, config).await;
+        let events = monitor.subscribe_events(64);
+        (monitor, events)
+    }
+
+    /// Runs `signature` through the pipeline and returns the event it ended in.
+    async fn detect(
+        monitor: &TokenMonitor,
+        events: &mut mpsc::Receiver<DetectionEvent>,
+        signature: Signature,
+    ) -> DetectionEvent {
+        // Failures are reported as `Failed` events as well.
+        let _ = monitor.handle_signature(&signature).await;
+        events.try_recv().unwrap()
+    }
+
+    fn created(event: DetectionEvent) -> TokenData {
+        match event {
+            DetectionEvent::Created { token_data, .. } => *token_data,
+            other => panic!("expected a created pool, got {:?}", other),
+        }
+    }
+
+    /// A token balance of `amount` base units at 6 decimals.
+    fn pool_balance(mint: &str, owner: &str, amount: u64) -> UiTransactionTokenBalance {
+        serde_json::from_value(serde_json::json!({
+            "accountIndex": 1,
+            "mint": mint,
+            "uiTokenAmount": {
+                "uiAmount": amount as f64 / 1e6,
+                "decimals": 6,
+                "amount": amount.to_string(),
+                "uiAmountString": "",
+            },
+            "owner": owner,
+        }))
+        .unwrap()
+    }
+
+    fn classify(balances: &[UiTransactionTokenBalance]) -> (String, String) {
+        let quote_mints = MonitorConfig::default().quote_mints;
+        let side = |is_quote| {
+            TokenMonitor::extract_token_info(balances, is_quote, LP_OWNER, &quote_mints)
+                .unwrap()
+                .address
+        };
+        (side(false), side(true))
+    }
+
+    #[test]
+    fn each_default_quote_mint_is_classified_as_quote() {
+        let base = Pubkey::new_unique().to_string();
+        for quote in [WSOL_MINT, USDC_MINT, USDT_MINT] {
+            let balances = [
+                pool_balance(quote, LP_OWNER, 10),
+                pool_balance(&base, LP_OWNER, 20),
+                // Not the pool's, so never a candidate.
+                pool_balance(&Pubkey::new_unique().to_string(), RAY_FEE, 30),
+            ];
+
+            assert_eq!(classify(&balances), (base.clone(), quote.to_string()));
+        }
+    }
+
+    #[test]
+    fn pools_of_two_quote_mints_are_quoted_in_the_higher_priority_one() {
+        let sol_usdc = [
+            pool_balance(USDC_MINT, LP_OWNER, 10),
+            pool_balance(WSOL_MINT, LP_OWNER, 20),
+        ];
+        let usdt_usdc = [
+            pool_balance(USDT_MINT, LP_OWNER, 10),
+            pool_balance(USDC_MINT, LP_OWNER, 20),
+        ];
+
+        assert_eq!(classify(&sol_usdc), (USDC_MINT.into(), WSOL_MINT.into()));
+        assert_eq!(classify(&usdt_usdc), (USDT_MINT.into(), USDC_MINT.into()));
+    }
+
+    #[test]
+    fn two_base_mints_are_ambiguous() {
+        let balances = [
+            pool_balance(&Pubkey::new_unique().to_string(), LP_OWNER, 10),
+            pool_balance(&Pubkey::new_unique().to_string(), LP_OWNER, 20),
+            pool_balance(WSOL_MINT, LP_OWNER, 30),
+        ];
+        let quote_mints = MonitorConfig::default().quote_mints;
+
+        let result = TokenMonitor::extract_token_info(&balances, false, LP_OWNER, &quote_mints);
+
+        assert!(matches!(
+            result,
+            Err(MonitorError::AmbiguousTokenInfo { .. })
+        ));
+    }
+
+    #[test]
+    fn lp_amount_is_exact_where_f64_rounds() {
+        // 0 decimals and a supply past 2^53, where f64 can't hold every integer.
+        let amount = 9_007_199_254_740_993_u64;
+
+        let info = TokenInfo::new(WSOL_MINT.to_string(), 0, amount);
+
+        assert_ne!(amount as f64 as u64, amount);
+        assert_eq!(info.lp_amount.to_string(), "9007199254740993");
+        assert_eq!(
+            serde_json::to_value(&info).unwrap()["lp_amount"],
+            "9007199254740993"
+        );
+    }
+
+    #[test]
+    fn lp_amount_loads_from_a_string_or_an_old_f64() {
+        let info = |lp_amount: &str| {
+            let json = format!(
+                r#"{{"address":"{}","decimals":6,"lp_amount":{}}}"#,
+                WSOL_MINT, lp_amount
+            );
+            serde_json::from_str::<TokenInfo>(&json).map(|info| info.lp_amount)
+        };
+
+        assert_eq!(info(r#""1.5""#).unwrap(), Decimal::new(15, 1));
+        assert_eq!(info("1.5").unwrap(), Decimal::new(15, 1));
+        assert_eq!(info("42").unwrap(), Decimal::from(42));
+        assert!(info(r#""lots""#).is_err());
+    }
+
+    #[test]
+    fn records_missing_later_fields_still_load() {
+        let line = r#"{"lp_signature":"sig","timestamp":"2024-01-01T00:00:00Z"}"#;
+
+        let data = read_token_data(line, SchemaMode::Strict).unwrap();
+
+        assert_eq!(data.lp_signature, "sig");
+        assert!(data.creator.is_empty());
+        assert!(data.base_info.address.is_empty());
+        assert_eq!(data.quote_info.lp_amount, Decimal::ZERO);
+    }
+
+    #[test]
+    fn unknown_fields_load_when_lenient_and_fail_when_strict() {
+        let line = r#"{"lp_signature":"sig","timestamp":"t","from_the_future":1}"#;
+
+        assert!(read_token_data(line, SchemaMode::Lenient).is_ok());
+        let err = read_token_data(line, SchemaMode::Strict).unwrap_err();
+        assert_eq!(err.to_string(), "Unknown TokenData field 'from_the_future'");
+    }
+
+    #[tokio::test]
+    async fn transactions_without_meta_are_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        let fixture = PoolFixture {
+            with_meta: false,
+            ..PoolFixture::new()
+        };
+        let monitor = mock_monitor(dir.path(), &fixture.rpc(), MonitorConfig::default()).await;
+
+        let outcome = monitor.parse_transaction(&fixture.signature).await.unwrap();
+
+        let ParseOutcome::Skipped(reason) = outcome else {
+            panic!("expected the transaction to be skipped");
+        };
+        assert_eq!(reason, "transaction has no metadata");
+    }
+
+    #[tokio::test]
+    async fn partitioned_records_land_in_their_own_day_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let fixture = PoolFixture::new();
+        let config = MonitorConfig {
+            partition_by_date: true,
+            ..MonitorConfig::default()
+        };
+        let monitor = mock_monitor(dir.path(), &fixture.rpc(), config).await;
+        let data = serde_json::to_value(pool(
+            monitor.parse_transaction(&fixture.signature).await.unwrap(),
+        ))
+        .unwrap();
+        let detected_at = |timestamp: &str| {
+            let mut data = data.clone();
+            data["timestamp"] = timestamp.into();
+            serde_json::from_value::<TokenData>(data).unwrap()
+        };
+
+        for timestamp in [
+            "2024-06-01T10:00:00+00:00",
+            "2024-06-02T00:00:01+00:00",
+            // Still the 1st in UTC.
+            "2024-06-02T01:00:00+02:00",
+        ] {
+            monitor.store_data(&detected_at(timestamp)).await.unwrap();
+        }
+
+        let timestamps = |name: &str| -> Vec<Value> {
+            stored_records(&dir.path().join(name))
+                .into_iter()
+                .map(|record| record["timestamp"].clone())
+                .collect()
+        };
+        assert_eq!(
+            timestamps("pools-2024-06-01.json"),
+            ["2024-06-01T10:00:00+00:00", "2024-06-02T01:00:00+02:00"]
+        );
+        assert_eq!(
+            timestamps("pools-2024-06-02.json"),
+            ["2024-06-02T00:00:01+00:00"]
+        );
+        assert!(!dir.path().join("pools.json").exists());
+    }
+
+    #[tokio::test]
+    async fn detections_are_timestamped_by_the_monitor_clock() {
+        let dir = tempfile::tempdir().unwrap();
+        let fixture = PoolFixture::new();
+        let config = MonitorConfig {
+            partition_by_date: true,
+            ..MonitorConfig::default()
+        };
+        let mut monitor = mock_monitor(dir.path(), &fixture.rpc(), config).await;
+        let start = DateTime::parse_from_rfc3339("2024-06-01T23:59:59Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        monitor.set_clock(Arc::new(MockClock::new(start)));
+
+        monitor.process_signature(&fixture.signature).await.unwrap();
+
+        let records = stored_records(&dir.path().join("pools-2024-06-01.json"));
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0]["timestamp"], "2024-06-01T23:59:59+00:00");
+    }
+
+    #[tokio::test]
+    async fn created_events_report_the_latency_from_the_block_time() {
+        let dir = tempfile::tempdir().unwrap();
+        let fixture = PoolFixture::new();
+        let mut transaction = serde_json::to_value(fixture.transaction()).unwrap();
+        transaction["blockTime"] = serde_json::json!(1_717_286_397);
+        let rpc = ScriptedRpc::default();
+        rpc.reply(RpcRequest::GetTransaction, transaction);
+        let mut monitor = mock_monitor(dir.path(), &rpc, MonitorConfig::default()).await;
+        let now = DateTime::from_timestamp_millis(1_717_286_399_500).unwrap();
+        monitor.set_clock(Arc::new(MockClock::new(now)));
+
+        let event = monitor.process_signature(&fixture.signature).await.unwrap();
+
+        let DetectionEvent::Created {
+            token_data,
+            detection_latency_ms,
+        } = event
+        else {
+            panic!("expected a created event, got {:?}", event);
+        };
+        assert_eq!(token_data.block_time, Some(1_717_286_397));
+        assert_eq!(detection_latency_ms, Some(2_500));
+    }
+
+    #[tokio::test]
+    async fn cpmm_pools_match_balances_owned_by_the_cpmm_authority() {
+        let dir = tempfile::tempdir().unwrap();
+        let fixture = PoolFixture {
+            variant: PoolVariant::Cpmm,
+            ..PoolFixture::new()
+        };
+        let config = MonitorConfig {
+            variant: PoolVariant::Cpmm,
+            ..MonitorConfig::default()
+        };
+        let monitor = mock_monitor(dir.path(), &fixture.rpc(), config).await;
+
+        let data = pool(monitor.parse_transaction(&fixture.signature).await.unwrap());
+
+        assert_eq!(PoolVariant::Cpmm.authority(), CPMM_AUTHORITY);
+        assert_eq!(data.pool_address, fixture.pool);
+        assert_eq!(data.base_info.address, fixture.base_mint);
+        assert_eq!(data.quote_info.address, WSOL_MINT);
+
+        // The AMM v4 authority owns none of them.
+        let monitor = mock_monitor(dir.path(), &fixture.rpc(), MonitorConfig::default()).await;
+        assert!(monitor.parse_transaction(&fixture.signature).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn shutdown_drains_the_signature_in_flight() {
+        let dir = tempfile::tempdir().unwrap();
+        let fixture = PoolFixture::new();
+        let rpc = ScriptedRpc::default();
+        // Not found at first, which keeps the signature in flight a while.
+        rpc.reply(RpcRequest::GetSignatureStatuses, statuses(Value::Null))
+            .reply(
+                RpcRequest::GetTransaction,
+                serde_json::to_value(fixture.transaction()).unwrap(),
+            );
+        let url = notifying_pubsub_server(vec![fixture.signature]).await;
+        let config = MonitorConfig {
+            await_signature_status: true,
+            ..MonitorConfig::default()
+        };
+        let monitor = pubsub_monitor(dir.path(), &rpc, &url, config).await;
+
+        let cancel = CancellationToken::new();
+        let run = tokio::spawn(Arc::new(monitor).monitor_new_tokens(cancel.clone()));
+        while rpc.count(RpcRequest::GetSignatureStatuses) == 0 {
+            sleep(Duration::from_millis(5)).await;
+        }
+        cancel.cancel();
+
+        timeout(Duration::from_secs(5), run)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored_records(&dir.path().join("pools.json")).len(), 1);
+        assert_eq!(rpc.count(RpcRequest::GetTransaction), 1);
+    }
+
+    #[tokio::test]
+    async fn backfill_handles_the_signatures_missed_since_the_slot() {
+        let dir = tempfile::tempdir().unwrap();
+        let older = PoolFixture::new();
+        let newer = PoolFixture::new();
+        let rpc = ScriptedRpc::default();
+        rpc.reply(
+            RpcRequest::GetSignaturesForAddress,
+            serde_json::json!([
+                { "signature": newer.signature.to_string(), "slot": 12, "err": null },
+                { "signature": older.signature.to_string(), "slot": 11, "err": null },
+                // Seen before the disconnect.
+                { "signature": Signature::new_unique().to_string(), "slot": 10, "err": null },
+            ]),
+        )
+        .reply(
+            RpcRequest::GetTransaction,
+            serde_json::to_value(older.transaction()).unwrap(),
+        )
+        .reply(
+            RpcRequest::GetTransaction,
+            serde_json::to_value(newer.transaction()).unwrap(),
+        );
+        let mut monitor = mock_monitor(dir.path(), &rpc, MonitorConfig::default()).await;
+        let mut events = monitor.subscribe_events(4);
+
+        let newest_slot = monitor.backfill(&Pubkey::new_unique(), 10).await.unwrap();
+
+        assert_eq!(newest_slot, Some(12));
+        assert_eq!(rpc.count(RpcRequest::GetTransaction), 2);
+        let handled: Vec<_> = std::iter::from_fn(|| events.try_recv().ok())
+            .map(|event| match event {
+                DetectionEvent::Created { token_data, .. } => token_data.lp_signature,
+                other => panic!("expected a created pool, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            handled,
+            [older.signature.to_string(), newer.signature.to_string()]
+        );
+    }
+
+    #[tokio::test]
+    async fn each_dedup_key_suppresses_its_own_repeats() {
+        let first = PoolFixture::new();
+        let same_mint = PoolFixture {
+            base_mint: first.base_mint.clone(),
+            ..PoolFixture::new()
+        };
+        let same_pool = PoolFixture {
+            pool: first.pool.clone(),
+            ..PoolFixture::new()
+        };
+
+        for (dedup_key, expected) in [
+            (DedupKey::Signature, [true, true, true]),
+            (DedupKey::BaseMint, [true, false, true]),
+            (DedupKey::PoolAccount, [true, true, false]),
+        ] {
+            let dir = tempfile::tempdir().unwrap();
+            let rpc = ScriptedRpc::default();
+            for fixture in [&first, &same_mint, &same_pool] {
+                rpc.reply(
+                    RpcRequest::GetTransaction,
+                    serde_json::to_value(fixture.transaction()).unwrap(),
+                );
+            }
+            let config = MonitorConfig {
+                dedup_key,
+                ..MonitorConfig::default()
+            };
+            let monitor = mock_monitor(dir.path(), &rpc, config).await;
+
+            let mut stored = Vec::new();
+            for fixture in [&first, &same_mint, &same_pool] {
+                let event = monitor.process_signature(&fixture.signature).await.unwrap();
+                stored.push(matches!(event, DetectionEvent::Created { .. }));
+            }
+
+            assert_eq!(stored, expected, "{:?}", dedup_key);
+        }
+    }
+
+    #[tokio::test]
+    async fn dedup_keys_past_the_capacity_are_forgotten_oldest_first() {
+        let first = PoolFixture::new();
+        let other = PoolFixture::new();
+        let same_mint = PoolFixture {
+            base_mint: first.base_mint.clone(),
+            ..PoolFixture::new()
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let rpc = ScriptedRpc::default();
+        for fixture in [&first, &other, &same_mint] {
+            rpc.reply(
+                RpcRequest::GetTransaction,
+                serde_json::to_value(fixture.transaction()).unwrap(),
+            );
+        }
+        let config = MonitorConfig {
+            dedup_key: DedupKey::BaseMint,
+            dedup_capacity: 1,
+            ..MonitorConfig::default()
+        };
+        let monitor = mock_monitor(dir.path(), &rpc, config).await;
+
+        for fixture in [&first, &other, &same_mint] {
+            let event = monitor.process_signature(&fixture.signature).await.unwrap();
+            assert!(matches!(event, DetectionEvent::Created { .. }));
+        }
+        assert_eq!(monitor.seen.lock().unwrap().len(), 1);
+    }
+
+    /// A stored pool with these initial reserves and nothing else.
+    fn pool_with_reserves(base: u64, quote: u64) -> TokenData {
+        let mut data: TokenData =
+            serde_json::from_str(r#"{"lp_signature":"","timestamp":""}"#).unwrap();
+        data.base_info = TokenInfo::new(Pubkey::new_unique().to_string(), 0, base);
+        data.quote_info = TokenInfo::new(WSOL_MINT.to_string(), 0, quote);
+        data
+    }
+
+    #[test]
+    fn price_impact_matches_the_constant_product_by_hand() {
+        // x * y = k: 100 SOL against 1,000,000 base. Buying with 10 SOL leaves
+        // 110 * 909,090.909..., so 90,909.09 base out against 100,000 at spot.
+        let pool = pool_with_reserves(1_000_000, 100);
+        let impact = price_impact(&pool, Decimal::from(10), Side::Buy);
+        assert_eq!(impact.round_dp(6), Decimal::new(90_909, 6));
+
+        // Selling 250,000 base into 1,000,000: 1,250,000 * 80 = k, so 20 SOL out
+        // against 25 at spot, 20% worse.
+        let impact = price_impact(&pool, Decimal::from(250_000), Side::Sell);
+        assert_eq!(impact, Decimal::new(2, 1));
+
+        let empty = pool_with_reserves(0, 0);
+        assert_eq!(
+            price_impact(&empty, Decimal::ZERO, Side::Buy),
+            Decimal::ZERO
+        );
+    }
+
+    #[tokio::test]
+    async fn write_calls_are_refused_before_reaching_the_rpc() {
+        let dir = tempfile::tempdir().unwrap();
+        let rpc = ScriptedRpc::default();
+        let monitor = mock_monitor(dir.path(), &rpc, MonitorConfig::default()).await;
+
+        for request in WRITE_RPC_METHODS.iter().copied() {
+            let result = monitor.rpc_client.send::<Value>(request, Value::Null).await;
+
+            let err = result.unwrap_err();
+            assert!(
+                matches!(err.downcast_ref(), Some(MonitorError::WriteBlocked(r)) if *r == request),
+                "{}",
+                err
+            );
+        }
+        let slot: u64 = monitor
+            .rpc_client
+            .send(RpcRequest::GetSlot, Value::Null)
+            .await
+            .unwrap();
+
+        assert_eq!(slot, 0);
+        assert_eq!(rpc.calls(), [RpcRequest::GetSlot]);
+    }
+
+    #[tokio::test]
+    async fn rpc_requests_carry_the_user_agent_and_session_id() {
+        use tokio::io::AsyncReadExt;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 4096];
+            while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                let read = stream.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..read]);
+            }
+            let body =
+                r#"{"jsonrpc":"2.0","result":{"solana-core":"1.18.26","feature-set":0},"id":1}"#;
+            let response = format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        let config = MonitorConfig {
+            user_agent: "sling-test/1.0".to_string(),
+            session_id: Some("run-42".to_string()),
+            ..MonitorConfig::default()
+        };
+
+        let client = http_rpc_client(&url, &config).unwrap();
+        client.get_version().await.unwrap();
+
+        let request = server.await.unwrap().to_ascii_lowercase();
+        assert!(
+            request.contains("user-agent: sling-test/1.0\r\n"),
+            "{}",
+            request
+        );
+        assert!(request.contains("x-session-id: run-42\r\n"), "{}", request);
+        assert!(MonitorConfig::default()
+            .user_agent
+            .starts_with("davids_sling/"));
+    }
+
+    #[tokio::test]
+    async fn rotated_partitions_are_gzipped_and_still_readable() {
+        let dir = tempfile::tempdir().unwrap();
+        let fixtures = [PoolFixture::new(), PoolFixture::new(), PoolFixture::new()];
+        let rpc = ScriptedRpc::default();
+        for fixture in &fixtures {
+            rpc.reply(
+                RpcRequest::GetTransaction,
+                serde_json::to_value(fixture.transaction()).unwrap(),
+            );
+        }
+        let config = MonitorConfig {
+            partition_by_date: true,
+            compress_rotated: true,
+            ..MonitorConfig::default()
+        };
+        let mut monitor = mock_monitor(dir.path(), &rpc, config).await;
+        let start = DateTime::parse_from_rfc3339("2024-06-01T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let clock = Arc::new(MockClock::new(start));
+        monitor.set_clock(clock.clone());
+
+        for (day, fixture) in fixtures.iter().enumerate() {
+            if day == 2 {
+                clock.advance(chrono::Duration::days(1));
+            }
+            monitor.process_signature(&fixture.signature).await.unwrap();
+        }
+
+        assert!(!dir.path().join("pools-2024-06-01.json").exists());
+        let records = load_records(&dir.path().join("pools-2024-06-01.json.gz")).unwrap();
+        let mut expected = vec![
+            fixtures[0].signature.to_string(),
+            fixtures[1].signature.to_string(),
+        ];
+        expected.sort();
+        assert_eq!(records.into_keys().collect::<Vec<_>>(), expected);
+        // Still being written to.
+        assert!(dir.path().join("pools-2024-06-02.json").exists());
+    }
+
+    #[tokio::test]
+    async fn detections_yields_events_as_a_stream() {
+        let dir = tempfile::tempdir().unwrap();
+        let fixtures = [PoolFixture::new(), PoolFixture::new(), PoolFixture::new()];
+        let rpc = ScriptedRpc::default();
+        for fixture in &fixtures {
+            rpc.reply(
+                RpcRequest::GetTransaction,
+                serde_json::to_value(fixture.transaction()).unwrap(),
+            );
+        }
+        let url =
+            notifying_pubsub_server(fixtures.iter().map(|fixture| fixture.signature).collect())
+                .await;
+        let monitor = pubsub_monitor(dir.path(), &rpc, &url, MonitorConfig::default()).await;
+
+        let first_two: Vec<_> = timeout(
+            Duration::from_secs(10),
+            monitor
+                .detections(1)
+                .map(|event| match event {
+                    DetectionEvent::Created { token_data, .. } => token_data.lp_signature,
+                    other => panic!("expected a created pool, got {:?}", other),
+                })
+                .take(2)
+                .collect(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            first_two,
+            [
+                fixtures[0].signature.to_string(),
+                fixtures[1].signature.to_string()
+            ]
+        );
+    }
+
+    /// A `getAccountInfo`-shaped account owned by `owner` holding `data`.
+    fn ui_account(owner: &Pubkey, data: &[u8]) -> Value {
+        serde_json::json!({
+            "lamports": 1_461_600,
+            "data": [bs58::encode(data).into_string(), "base58"],
+            "owner": owner.to_string(),
+            "executable": false,
+            "rentEpoch": 0,
+            "space": data.len(),
+        })
+    }
+
+    fn packed_mint(mint: Mint) -> Vec<u8> {
+        let mut data = vec![0; Mint::LEN];
+        Mint::pack(mint, &mut data).unwrap();
+        data
+    }
+
+    #[tokio::test]
+    async fn a_mint_that_is_not_a_token_mint_is_flagged_unverified() {
+        let initialized = Mint {
+            mint_authority: Some(Pubkey::new_unique()).into(),
+            supply: 1_000_000,
+            decimals: 6,
+            is_initialized: true,
+            freeze_authority: None.into(),
+        };
+        let config = MonitorConfig {
+            verify_mints: true,
+            ..MonitorConfig::default()
+        };
+
+        for (quote_account, verified) in [
+            (
+                ui_account(&spl_token::id(), &packed_mint(initialized)),
+                true,
+            ),
+            // Right size, but not the token program's.
+            (
+                ui_account(&Pubkey::new_unique(), &packed_mint(initialized)),
+                false,
+            ),
+            (ui_account(&spl_token::id(), &[7; 40]), false),
+        ] {
+            let dir = tempfile::tempdir().unwrap();
+            let fixture = PoolFixture::new();
+            let base_account = ui_account(&spl_token::id(), &packed_mint(initialized));
+            let rpc = fixture.rpc();
+            rpc.reply(
+                RpcRequest::GetMultipleAccounts,
+                serde_json::json!({
+                    "context": { "slot": 1 },
+                    "value": [base_account, quote_account],
+                }),
+            );
+            let monitor = mock_monitor(dir.path(), &rpc, config.clone()).await;
+
+            let data = pool(monitor.parse_transaction(&fixture.signature).await.unwrap());
+
+            assert_eq!(data.mint_verified, Some(verified));
+        }
+    }
+
+    #[tokio::test]
+    async fn minimal_mode_makes_no_account_calls() {
+        let dir = tempfile::tempdir().unwrap();
+        let fixture = PoolFixture::new();
+        let rpc = fixture.rpc();
+        let config = MonitorConfig {
+            minimal: true,
+            // Skipped in minimal mode.
+            verify_mints: true,
+            ..MonitorConfig::default()
+        };
+        let monitor = mock_monitor(dir.path(), &rpc, config).await;
+
+        let event = monitor.process_signature(&fixture.signature).await.unwrap();
+
+        let DetectionEvent::Minimal(minimal) = event else {
+            panic!("expected a minimal event, got {:?}", event);
+        };
+        assert_eq!(minimal.signature, fixture.signature.to_string());
+        assert_eq!(minimal.slot, 42);
+        assert_eq!(minimal.base_mint, fixture.base_mint);
+        assert_eq!(rpc.calls(), [RpcRequest::GetTransaction]);
+    }
+
+    #[test]
+    fn a_pool_without_a_base_balance_has_no_base() {
+        let balances = [
+            pool_balance(WSOL_MINT, LP_OWNER, 30),
+            // Not the pool's.
+            pool_balance(&Pubkey::new_unique().to_string(), RAY_FEE, 10),
+        ];
+        let quote_mints = MonitorConfig::default().quote_mints;
+
+        let result = TokenMonitor::extract_token_info(&balances, false, LP_OWNER, &quote_mints);
+
+        assert!(matches!(
+            result,
+            Err(MonitorError::TokenInfoNotFound("base"))
+        ));
+    }
+
+    #[test]
+    fn token_info_comes_from_the_raw_amount_without_a_ui_amount() {
+        let base = Pubkey::new_unique().to_string();
+        let mut balances = [
+            pool_balance(&base, LP_OWNER, 1_234_567),
+            pool_balance(WSOL_MINT, LP_OWNER, 30),
+        ];
+        balances[0].ui_token_amount.ui_amount = None;
+        let quote_mints = MonitorConfig::default().quote_mints;
+
+        let info =
+            TokenMonitor::extract_token_info(&balances, false, LP_OWNER, &quote_mints).unwrap();
+
+        assert_eq!(info.address, base);
+        assert_eq!(info.decimals, 6);
+        assert_eq!(info.amount, 1_234_567);
+        assert_eq!(info.lp_amount, Decimal::new(1_234_567, 6));
+    }
+
+    #[test]
+    fn a_balance_without_a_raw_amount_is_invalid() {
+        let base = Pubkey::new_unique().to_string();
+        let mut unparseable = pool_balance(&base, LP_OWNER, 0);
+        unparseable.ui_token_amount.amount = String::new();
+        let balances = [unparseable, pool_balance(WSOL_MINT, LP_OWNER, 30)];
+        let quote_mints = MonitorConfig::default().quote_mints;
+
+        let result = TokenMonitor::extract_token_info(&balances, false, LP_OWNER, &quote_mints);
+
+        assert!(matches!(result, Err(MonitorError::InvalidAmount(mint)) if mint == base));
+    }
+
+    #[test]
+    fn retry_budget_refuses_once_spent_until_the_window_ends() {
+        let budget = RetryBudget::new(2, Duration::from_millis(50));
+
+        assert!(budget.acquire().is_ok());
+        assert!(budget.acquire().is_ok());
+        assert!(matches!(
+            budget.acquire(),
+            Err(MonitorError::RetryBudgetExhausted)
+        ));
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(budget.acquire().is_ok());
+    }
+
+    #[tokio::test]
+    async fn a_spent_retry_budget_fails_the_fetch_without_retrying() {
+        let dir = tempfile::tempdir().unwrap();
+        let rpc = ScriptedRpc::default();
+        rpc.reply(RpcRequest::GetSignatureStatuses, statuses(Value::Null));
+        let config = MonitorConfig {
+            await_signature_status: true,
+            retry_budget: 0,
+            ..MonitorConfig::default()
+        };
+        let monitor = mock_monitor(dir.path(), &rpc, config).await;
+
+        let started = Instant::now();
+        let Err(err) = monitor.parse_transaction(&Signature::new_unique()).await else {
+            panic!("expected the fetch to fail");
+        };
+
+        assert!(
+            matches!(err.downcast_ref(), Some(MonitorError::RetryBudgetExhausted)),
+            "{}",
+            err
+        );
+        assert!(started.elapsed() < SIGNATURE_STATUS_POLL_INTERVAL);
+        assert_eq!(rpc.calls(), [RpcRequest::GetSignatureStatuses]);
+    }
+
+    #[tokio::test]
+    async fn recorded_transactions_replay_to_the_same_pool() {
+        let dir = tempfile::tempdir().unwrap();
+        let fixture = PoolFixture::new();
+        let rpc = fixture.rpc();
+        let recorded = dir.path().join("recorded");
+        let now = Utc::now();
+
+        let live_dir = dir.path().join("live");
+        std::fs::create_dir_all(&live_dir).unwrap();
+        let config = MonitorConfig {
+            record_dir: Some(recorded.clone()),
+            ..MonitorConfig::default()
+        };
+        let mut live = mock_monitor(&live_dir, &rpc, config).await;
+        live.set_clock(Arc::new(MockClock::new(now)));
+        let live_event = live.process_signature(&fixture.signature).await.unwrap();
+
+        let replay_dir = dir.path().join("replay");
+        std::fs::create_dir_all(&replay_dir).unwrap();
+        let config = MonitorConfig {
+            replay_dir: Some(recorded),
+            ..MonitorConfig::default()
+        };
+        let offline = ScriptedRpc::default();
+        let mut replay = mock_monitor(&replay_dir, &offline, config).await;
+        replay.set_clock(Arc::new(MockClock::new(now)));
+        let replayed_event = replay.process_signature(&fixture.signature).await.unwrap();
+
+        let (
+            DetectionEvent::Created {
+                token_data: live_data,
+                ..
+            },
+            DetectionEvent::Created {
+                token_data: replayed_data,
+                ..
+            },
+        ) = (live_event, replayed_event)
+        else {
+            panic!("expected both runs to create the pool");
+        };
+        assert_eq!(
+            serde_json::to_string(&replayed_data).unwrap(),
+            serde_json::to_string(&live_data).unwrap()
+        );
+        assert_eq!(rpc.count(RpcRequest::GetTransaction), 1);
+        assert!(offline.calls().is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_failed_enrichment_still_stores_the_pool() {
+        let dir = tempfile::tempdir().unwrap();
+        let fixture = PoolFixture::new();
+        let rpc = fixture.rpc();
+        // Not a `getMultipleAccounts` response, so the lookup errors.
+        rpc.reply(RpcRequest::GetMultipleAccounts, Value::from("unavailable"));
+        let config = MonitorConfig {
+            verify_mints: true,
+            ..MonitorConfig::default()
+        };
+        let monitor = mock_monitor(dir.path(), &rpc, config).await;
+
+        let event = monitor.process_signature(&fixture.signature).await.unwrap();
+
+        let DetectionEvent::Created { token_data, .. } = event else {
+            panic!("expected the pool to be created");
+        };
+        assert_eq!(rpc.count(RpcRequest::GetMultipleAccounts), 1);
+        assert_eq!(token_data.mint_verified, None);
+        let stored = stored_records(&dir.path().join("pools.json"));
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0]["mint_verified"], Value::Null);
+    }
+
+    #[tokio::test]
+    async fn repeat_account_lookups_within_the_ttl_come_from_the_cache() {
+        let rpc = ScriptedRpc::default();
+        let account = serde_json::json!({
+            "context": { "slot": 1 },
+            "value": ui_account(&spl_token::id(), &[1; 8]),
+        });
+        for _ in 0..3 {
+            rpc.reply(RpcRequest::GetAccountInfo, account.clone());
+        }
+        let client =
+            ReadOnlyRpcClient::new(rpc.client()).with_account_cache(8, Duration::from_millis(50));
+        let (first, second) = (Pubkey::new_unique(), Pubkey::new_unique());
+
+        client.get_account(&first).await.unwrap();
+        client.get_account(&first).await.unwrap();
+        assert_eq!(rpc.count(RpcRequest::GetAccountInfo), 1);
+
+        client.get_account(&second).await.unwrap();
+        assert_eq!(rpc.count(RpcRequest::GetAccountInfo), 2);
+
+        sleep(Duration::from_millis(60)).await;
+        client.get_account(&first).await.unwrap();
+        assert_eq!(rpc.count(RpcRequest::GetAccountInfo), 3);
+    }
+
+    #[tokio::test]
+    async fn pools_wait_for_the_configured_confirmation_depth() {
+        let dir = tempfile::tempdir().unwrap();
+        let fixture = PoolFixture::new();
+        let confirmed = |confirmations: u64| {
+            statuses(serde_json::json!({
+                "slot": 42,
+                "confirmations": confirmations,
+                "err": null,
+                "status": { "Ok": null },
+                "confirmationStatus": "confirmed",
+            }))
+        };
+        let rpc = fixture.rpc();
+        rpc.reply(RpcRequest::GetSignatureStatuses, confirmed(2))
+            .reply(RpcRequest::GetSignatureStatuses, confirmed(5));
+        let config = MonitorConfig {
+            min_confirmations: 5,
+            ..MonitorConfig::default()
+        };
+        let monitor = Arc::new(mock_monitor(dir.path(), &rpc, config).await);
+        let pools = dir.path().join("pools.json");
+
+        let processing = tokio::spawn({
+            let monitor = Arc::clone(&monitor);
+            let signature = fixture.signature;
+            async move { monitor.process_signature(&signature).await }
+        });
+        while rpc.count(RpcRequest::GetSignatureStatuses) == 0 {
+            sleep(Duration::from_millis(5)).await;
+        }
+        // Two of five: not stored yet.
+        sleep(Duration::from_millis(50)).await;
+        assert!(stored_records(&pools).is_empty());
+
+        let event = processing.await.unwrap().unwrap();
+        assert!(matches!(event, DetectionEvent::Created { .. }));
+        assert_eq!(stored_records(&pools).len(), 1);
+        assert_eq!(rpc.count(RpcRequest::GetSignatureStatuses), 2);
+    }
+
+    #[cfg(feature = "protobuf")]
+    #[tokio::test]
+    async fn protobuf_records_read_back_unchanged() {
+        let dir = tempfile::tempdir().unwrap();
+        let fixture = PoolFixture::new();
+        let config = MonitorConfig {
+            include_all_balances: true,
+            ..MonitorConfig::default()
+        };
+        let monitor = mock_monitor(dir.path(), &fixture.rpc(), config).await;
+        let data = pool(monitor.parse_transaction(&fixture.signature).await.unwrap());
+        let path = dir.path().join("pools.pb");
+
+        let mut store = protobuf::ProtobufStore::create(&path).unwrap();
+        store.append(&data).unwrap();
+        store.append(&data).unwrap();
+        store.flush().unwrap();
+        let records = protobuf::read_protobuf_records(&path).unwrap();
+
+        assert_eq!(records.len(), 2);
+        for record in &records {
+            assert_eq!(
+                serde_json::to_value(record).unwrap(),
+                serde_json::to_value(&data).unwrap()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn a_tight_enrichment_budget_stores_a_partial_pool() {
+        let dir = tempfile::tempdir().unwrap();
+        let fixture = PoolFixture::new();
+        let rpc = fixture.rpc();
+        let config = MonitorConfig {
+            verify_mints: true,
+            check_top_holder: true,
+            // Enough for the mint check, not the two top-holder calls after it.
+            enrichment_max_rpc_calls: 1,
+            ..MonitorConfig::default()
+        };
+        let monitor = mock_monitor(dir.path(), &rpc, config).await;
+
+        let event = monitor.process_signature(&fixture.signature).await.unwrap();
+
+        let DetectionEvent::Created { token_data, .. } = event else {
+            panic!("expected the pool to be created");
+        };
+        assert_eq!(
+            rpc.calls(),
+            [RpcRequest::GetTransaction, RpcRequest::GetMultipleAccounts]
+        );
+        assert_eq!(token_data.mint_verified, Some(false));
+        assert_eq!(token_data.top_holder_pct, None);
+        assert!(!token_data.enrichment_complete);
+        assert_eq!(stored_records(&dir.path().join("pools.json")).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn cached_mints_leave_the_enrichment_cap_to_the_top_holder_lookup() {
+        let dir = tempfile::tempdir().unwrap();
+        let fixture = PoolFixture::new();
+        let signature = fixture.write(&dir.path().join("fixtures"));
+        let mint = Mint {
+            supply: 100,
+            decimals: 6,
+            is_initialized: true,
+            ..Mint::default()
+        };
+        let account = ui_account(&spl_token::id(), &packed_mint(mint));
+        let rpc = ScriptedRpc::default();
+        rpc.reply(
+            RpcRequest::GetMultipleAccounts,
+            serde_json::json!({ "context": { "slot": 1 }, "value": [account, account] }),
+        );
+        holders(&rpc, 10, 100);
+        let config = MonitorConfig {
+            verify_mints: true,
+            check_top_holder: true,
+            // Just the two top-holder calls.
+            enrichment_max_rpc_calls: 2,
+            ..MonitorConfig::default()
+        };
+        let (monitor, mut events) = replay_monitor_with(dir.path(), &rpc, config).await;
+        let mints =
+            [&fixture.base_mint, &fixture.quote_mint].map(|mint| Pubkey::from_str(mint).unwrap());
+        monitor
+            .rpc_client
+            .get_multiple_accounts(&mints)
+            .await
+            .unwrap();
+
+        let data = created(detect(&monitor, &mut events, signature).await);
+
+        assert_eq!(
+            rpc.calls(),
+            [
+                RpcRequest::GetMultipleAccounts,
+                RpcRequest::GetTokenLargestAccounts,
+                RpcRequest::GetTokenSupply
+            ]
+        );
+        assert_eq!(data.mint_verified, Some(true));
+        assert_eq!(data.top_holder_pct, Some(10.0));
+        assert!(data.enrichment_complete);
+    }
+
+    #[tokio::test]
+    async fn the_same_pool_serializes_to_the_same_bytes() {
+        let dir = tempfile::tempdir().unwrap();
+        let signature = PoolFixture::new().write(&dir.path().join("fixtures"));
+        let config = MonitorConfig {
+            include_all_balances: true,
+            ..MonitorConfig::default()
+        };
+        let (monitor, mut events) = replay_monitor(dir.path(), config).await;
+        let data = created(detect(&monitor, &mut events, signature).await);
+
+        let first = serde_json::to_string(&data).unwrap();
+        let second = serde_json::to_string(&data).unwrap();
+        let reloaded: TokenData = serde_json::from_str(&first).unwrap();
+
+        assert_eq!(first, second);
+        assert_eq!(serde_json::to_string(&reloaded).unwrap(), first);
+        // Declaration order, not alphabetical.
+        assert!(first.starts_with(r#"{"lp_signature":"#));
+        assert!(first.find(r#""pool_address""#) < first.find(r#""creator""#));
+    }
+
+    #[tokio::test]
+    async fn reparse_fills_new_fields_from_the_recorded_transaction() {
+        let dir = tempfile::tempdir().unwrap();
+        let fixture = PoolFixture::new();
+        let recorded = fixture.write(&dir.path().join("fixtures"));
+        let unrecorded = Signature::new_unique();
+        // Written before pool_address, fee_payer and fee_lamports existed.
+        let old_record = |signature: &Signature| {
+            serde_json::json!({
+                "lp_signature": signature.to_string(),
+                "creator": "old-creator",
+                "timestamp": "2024-01-01T00:00:00+00:00",
+            })
+            .to_string()
+        };
+        let (input, output) = (dir.path().join("old.jsonl"), dir.path().join("new.jsonl"));
+        std::fs::write(
+            &input,
+            format!("{}\n{}\n", old_record(&recorded), old_record(&unrecorded)),
+        )
+        .unwrap();
+        let (monitor, _events) = replay_monitor(dir.path(), MonitorConfig::default()).await;
+
+        let report = monitor.reparse(&input, &output).await.unwrap();
+
+        assert_eq!(
+            report,
+            ReparseReport {
+                reparsed: 1,
+                passed_through: 1
+            }
+        );
+        let records = stored_records(&output);
+        assert_eq!(records[0]["pool_address"], fixture.pool);
+        assert_eq!(records[0]["fee_payer"], fixture.fee_payer);
+        assert_eq!(records[0]["fee_lamports"], fixture.fee);
+        assert_eq!(records[0]["timestamp"], "2024-01-01T00:00:00+00:00");
+        assert_eq!(records[1]["lp_signature"], unrecorded.to_string());
+        assert_eq!(records[1]["creator"], "old-creator");
+        assert_eq!(records[1]["pool_address"], "");
+        assert_eq!(records[1]["fee_lamports"], 0);
+    }
+
+    #[test]
+    fn the_signature_is_found_in_each_known_payload_shape() {
+        let signature = Signature::new_unique().to_string();
+        let value = serde_json::json!({ "signature": signature, "err": null, "logs": [] });
+        let payloads = [
+            // `Response<RpcLogsResponse>` as the pubsub client hands it over.
+            serde_json::json!({ "context": { "slot": 7 }, "value": value }),
+            value.clone(),
+            serde_json::json!({ "result": { "context": { "slot": 7 }, "value": value } }),
+            serde_json::json!({
+                "jsonrpc": "2.0",
+                "method": "logsNotification",
+                "params": { "result": { "context": { "slot": 7 }, "value": value } },
+            }),
+        ];
+
+        for payload in &payloads {
+            assert_eq!(
+                notification_signature(payload),
+                Some(signature.as_str()),
+                "{}",
+                payload
+            );
+        }
+        assert_eq!(notification_slot(&payloads[0]), Some(7));
+        assert_eq!(notification_slot(&payloads[3]), Some(7));
+        assert_eq!(
+            notification_signature(&serde_json::json!({ "value": { "logs": [] } })),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn a_payload_without_a_signature_falls_back_to_polling_its_slot() {
+        let dir = tempfile::tempdir().unwrap();
+        let signature = PoolFixture::new().write(&dir.path().join("fixtures"));
+        let rpc = ScriptedRpc::default();
+        rpc.reply(
+            RpcRequest::GetSignaturesForAddress,
+            serde_json::json!([
+                { "signature": signature.to_string(), "slot": 11, "err": null },
+                { "signature": Signature::new_unique().to_string(), "slot": 10, "err": null },
+            ]),
+        );
+        let (monitor, mut events) =
+            replay_monitor_with(dir.path(), &rpc, MonitorConfig::default()).await;
+        let malformed = serde_json::json!({ "context": { "slot": 11 }, "value": { "logs": [] } });
+
+        monitor
+            .handle_log_notification(malformed, &Pubkey::new_unique())
+            .await
+            .unwrap();
+
+        let data = created(events.try_recv().unwrap());
+        assert_eq!(data.lp_signature, signature.to_string());
+        assert!(events.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn the_on_detect_command_gets_the_record_on_stdin() {
+        let dir = tempfile::tempdir().unwrap();
+        let signature = PoolFixture::new().write(&dir.path().join("fixtures"));
+        let received = dir.path().join("received.json");
+        // Renamed into place so a half-written file is never read.
+        let command = format!("cat > '{0}.tmp' && mv '{0}.tmp' '{0}'", received.display());
+        let config = MonitorConfig {
+            on_detect_command: Some(command),
+            ..MonitorConfig::default()
+        };
+        let (monitor, mut events) = replay_monitor(dir.path(), config).await;
+
+        let data = created(detect(&monitor, &mut events, signature).await);
+
+        // The command runs in the background; wait for it to finish writing.
+        let json = timeout(Duration::from_secs(5), async {
+            loop {
+                match std::fs::read(&received) {
+                    Ok(json) => return json,
+                    Err(_) => sleep(Duration::from_millis(10)).await,
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(json, serde_json::to_vec(&data).unwrap());
+    }
+
+    #[tokio::test]
+    async fn fresh_mints_are_told_apart_from_relistings() {
+        let dir = tempfile::tempdir().unwrap();
+        let fixtures = dir.path().join("fixtures");
+        let fresh = PoolFixture {
+            fresh_mint: true,
+            ..PoolFixture::new()
+        }
+        .write(&fixtures);
+        let relisted = PoolFixture::new().write(&fixtures);
+        let config = MonitorConfig {
+            require_fresh_mint: true,
+            ..MonitorConfig::default()
+        };
+        let (monitor, mut events) = replay_monitor(dir.path(), config).await;
+
+        assert!(created(detect(&monitor, &mut events, fresh).await).fresh_mint);
+        let event = detect(&monitor, &mut events, relisted).await;
+        let DetectionEvent::Filtered { reason, .. } = event else {
+            panic!("expected a filtered event, got {:?}", event);
+        };
+        assert_eq!(reason, "base mint was not created in this transaction");
+
+        let dir = tempfile::tempdir().unwrap();
+        let relisted = PoolFixture::new().write(&dir.path().join("fixtures"));
+        let (monitor, mut events) = replay_monitor(dir.path(), MonitorConfig::default()).await;
+        assert!(!created(detect(&monitor, &mut events, relisted).await).fresh_mint);
+    }
+
+    /// Largest accounts holding `largest`, then a supply of `supply`, both raw.
+    fn holders(rpc: &ScriptedRpc, largest: u64, supply: u64) {
+        let amount = |amount: u64| {
+            serde_json::json!({
+                "amount": amount.to_string(),
+                "decimals": 6,
+                "uiAmount": null,
+                "uiAmountString": "",
+            })
+        };
+        let mut holder = amount(largest);
+        holder["address"] = Pubkey::new_unique().to_string().into();
+        rpc.reply(
+            RpcRequest::GetTokenLargestAccounts,
+            serde_json::json!({ "context": { "slot": 1 }, "value": [holder] }),
+        )
+        .reply(
+            RpcRequest::GetTokenSupply,
+            serde_json::json!({ "context": { "slot": 1 }, "value": amount(supply) }),
+        );
+    }
+
+    #[tokio::test]
+    async fn top_holder_pct_is_the_largest_account_over_the_supply() {
+        let config = MonitorConfig {
+            check_top_holder: true,
+            max_top_holder_pct: Some(50.0),
+            ..MonitorConfig::default()
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let signature = PoolFixture::new().write(&dir.path().join("fixtures"));
+        let rpc = ScriptedRpc::default();
+        holders(&rpc, 250_000, 1_000_000);
+        let (monitor, mut events) = replay_monitor_with(dir.path(), &rpc, config.clone()).await;
+        let data = created(detect(&monitor, &mut events, signature).await);
+        assert_eq!(data.top_holder_pct, Some(25.0));
+
+        let dir = tempfile::tempdir().unwrap();
+        let signature = PoolFixture::new().write(&dir.path().join("fixtures"));
+        let rpc = ScriptedRpc::default();
+        holders(&rpc, 900_000, 1_000_000);
+        let (monitor, mut events) = replay_monitor_with(dir.path(), &rpc, config).await;
+        let event = detect(&monitor, &mut events, signature).await;
+        let DetectionEvent::Filtered { reason, .. } = event else {
+            panic!("expected a filtered event, got {:?}", event);
+        };
+        assert_eq!(reason, "top holder has 90.00% of supply, above 50.00%");
+
+        assert_eq!(holder_share_pct(1, 0), None);
+    }
+
+    #[test]
+    fn balances_without_an_owner_are_skipped() {
+        let base = Pubkey::new_unique().to_string();
+        let mut ownerless = pool_balance(&Pubkey::new_unique().to_string(), LP_OWNER, 10);
+        ownerless.owner = OptionSerializer::None;
+        let mut skipped = pool_balance(&Pubkey::new_unique().to_string(), LP_OWNER, 10);
+        skipped.owner = OptionSerializer::Skip;
+        let balances = [
+            ownerless,
+            skipped,
+            pool_balance(&base, LP_OWNER, 20),
+            pool_balance(WSOL_MINT, LP_OWNER, 30),
+        ];
+
+        assert_eq!(balance_owner(&balances[0]), None);
+        assert_eq!(balance_owner(&balances[1]), None);
+        assert_eq!(classify(&balances), (base, WSOL_MINT.to_string()));
+    }
+
+    /// A pubsub server that accepts each subscription and then closes the
+    /// connection. Returns its URL and a count of connections made.
+    async fn closing_pubsub_server() -> (String, Arc<AtomicUsize>) {
+        use futures::{SinkExt, StreamExt};
+        use tokio_tungstenite::tungstenite::Message;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let connections = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&connections);
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                counter.fetch_add(1, Ordering::SeqCst);
+                let Ok(mut socket) = tokio_tungstenite::accept_async(stream).await else {
+                    continue;
+                };
+                if let Some(Ok(Message::Text(request))) = socket.next().await {
+                    let request: Value = serde_json::from_str(&request).unwrap();
+                    let reply = serde_json::json!({
+                        "jsonrpc": "2.0",
+                        "result": 1,
+                        "id": request["id"],
+                    });
+                    socket.send(Message::Text(reply.to_string())).await.unwrap();
+                }
+                let _ = socket.close(None).await;
+            }
+        });
+        (url, connections)
+    }
+
+    #[tokio::test]
+    async fn a_closed_subscription_reconnects() {
+        let dir = tempfile::tempdir().unwrap();
+        let (url, connections) = closing_pubsub_server().await;
+        let config = MonitorConfig {
+            max_reconnects: Some(1),
+            ..MonitorConfig::default()
+        };
+        let monitor = pubsub_monitor(dir.path(), &ScriptedRpc::default(), &url, config).await;
+
+        let run = Arc::new(monitor).monitor_new_tokens(CancellationToken::new());
+        let err = timeout(Duration::from_secs(10), run)
+            .await
+            .unwrap()
+            .unwrap_err();
+
+        assert!(
+            matches!(
+                err.downcast_ref::<MonitorError>(),
+                Some(MonitorError::ReconnectsExhausted(1))
+            ),
+            "{}",
+            err
+        );
+        assert_eq!(connections.load(Ordering::SeqCst), 2);
+        // A close isn't a failure.
+        assert!(!dir.path().join("errors.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn a_failed_subscription_reconnects() {
+        let dir = tempfile::tempdir().unwrap();
+        // Nothing listens on a port that was just released.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        drop(listener);
+        let config = MonitorConfig {
+            max_reconnects: Some(1),
+            ..MonitorConfig::default()
+        };
+        let monitor = pubsub_monitor(dir.path(), &ScriptedRpc::default(), &url, config).await;
+
+        let run = Arc::new(monitor).monitor_new_tokens(CancellationToken::new());
+        let err = timeout(Duration::from_secs(10), run)
+            .await
+            .unwrap()
+            .unwrap_err();
+
+        assert!(
+            matches!(
+                err.downcast_ref::<MonitorError>(),
+                Some(MonitorError::ReconnectsExhausted(1))
+            ),
+            "{}",
+            err
+        );
+        let errors = std::fs::read_to_string(dir.path().join("errors.txt")).unwrap();
+        // One failure for the first attempt and one for the reconnect.
+        assert_eq!(errors.matches("Error occurred: ").count(), 2);
+    }
+
+    /// A stored pool detected at `timestamp` (RFC 3339) against `quote_mint`.
+    #[cfg(feature = "api")]
+    fn stored_pool(signature: &str, timestamp: &str, quote_mint: &str) -> TokenData {
+        let mut data = pool_with_reserves(1, 1);
+        data.lp_signature = signature.to_string();
+        data.timestamp = timestamp.to_string();
+        data.quote_info = TokenInfo::new(quote_mint.to_string(), 6, 1);
+        data
+    }
+
+    /// Serves the API over `pools` and returns the body of `GET path`.
+    #[cfg(feature = "api")]
+    async fn api_get(dir: &Path, pools: &[TokenData], path: &str) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let data_path = dir.join("pools.json");
+        let lines: Vec<_> = pools
+            .iter()
+            .map(|data| serde_json::to_string(data).unwrap())
+            .collect();
+        std::fs::write(&data_path, lines.join("\n") + "\n").unwrap();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, api::router(data_path)).await });
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let request = format!(
+            "GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+            path, addr
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("HTTP/1.1 200"), "{}", head);
+        body.to_string()
+    }
+
+    #[cfg(feature = "api")]
+    #[tokio::test]
+    async fn pools_endpoint_filters_and_pages() {
+        let dir = tempfile::tempdir().unwrap();
+        let pools = [
+            stored_pool("old", "2024-01-01T00:00:00+00:00", USDC_MINT),
+            stored_pool("sol", "2024-01-02T00:00:00+00:00", WSOL_MINT),
+            stored_pool("usdc-1", "2024-01-03T00:00:00+00:00", USDC_MINT),
+            stored_pool("usdc-2", "2024-01-04T00:00:00+00:00", USDC_MINT),
+            stored_pool("usdc-3", "2024-01-05T00:00:00+00:00", USDC_MINT),
+        ];
+        let signatures = |body: &str| -> (Vec<String>, Value) {
+            let page: Value = serde_json::from_str(body).unwrap();
+            let pools = page["pools"].as_array().unwrap();
+            let signatures = pools
+                .iter()
+                .map(|pool| pool["lp_signature"].to_string())
+                .collect();
+            (signatures, page["next_offset"].clone())
+        };
+
+        let query = format!(
+            "/pools?quote_mint={}&since=2024-01-02T00:00:00Z&limit=2",
+            USDC_MINT
+        );
+        let (first, next_offset) = signatures(&api_get(dir.path(), &pools, &query).await);
+        assert_eq!(first, [r#""usdc-1""#, r#""usdc-2""#]);
+        assert_eq!(next_offset, 2);
+
+        let query = format!("{}&offset=2", query);
+        let (last, next_offset) = signatures(&api_get(dir.path(), &pools, &query).await);
+        assert_eq!(last, [r#""usdc-3""#]);
+        assert!(next_offset.is_null());
+    }
+
+    /// Whether every tag in `xml` after the declaration is closed in order.
+    #[cfg(feature = "api")]
+    fn balanced_tags(xml: &str) -> bool {
+        let mut open = Vec::new();
+        for tag in xml.split('<').skip(1).filter(|tag| !tag.starts_with('?')) {
+            let tag = &tag[..tag.find('>').unwrap()];
+            match tag.strip_prefix('/') {
+                Some(name) if open.pop() != Some(name) => return false,
+                Some(_) => {}
+                None => open.push(tag.split(' ').next().unwrap()),
+            }
+        }
+        open.is_empty()
+    }
+
+    #[cfg(feature = "api")]
+    #[tokio::test]
+    async fn feed_lists_the_newest_pools_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut pools: Vec<_> = (0..60)
+            .map(|n| {
+                stored_pool(
+                    &format!("sig-{}", n),
+                    "2024-01-01T00:00:00+00:00",
+                    WSOL_MINT,
+                )
+            })
+            .collect();
+        pools[59].block_time = Some(1_700_000_000);
+        // Has to be escaped to stay well-formed.
+        pools[58].base_info.address = "<&>".to_string();
+
+        let feed = api_get(dir.path(), &pools, "/feed.xml").await;
+
+        assert!(feed.starts_with(r#"<?xml version="1.0" encoding="UTF-8"?>"#));
+        assert!(balanced_tags(&feed), "{}", feed);
+        assert_eq!(feed.matches("<item>").count(), 50);
+        let newest = feed.find("https://solscan.io/tx/sig-59").unwrap();
+        assert!(newest < feed.find("https://solscan.io/tx/sig-58").unwrap());
+        assert!(!feed.contains("sig-9<"));
+        assert!(feed.contains("<pubDate>Tue, 14 Nov 2023 22:13:20 +0000</pubDate>"));
+        assert!(feed.contains("New pool for &lt;&amp;&gt;"));
+    }
+
+    #[tokio::test]
+    async fn a_signature_notified_twice_is_stored_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let (monitor, mut events) = replay_monitor(dir.path(), MonitorConfig::default()).await;
+        let signature = PoolFixture::new().write(&dir.path().join("fixtures"));
+        let address = Pubkey::from_str(RAY_FEE).unwrap();
+
+        // Once at processed and again at confirmed, from a later slot.
+        for slot in [5, 6] {
+            let logs = serde_json::json!({
+                "context": { "slot": slot },
+                "value": { "signature": signature.to_string(), "err": null, "logs": [] },
+            });
+            monitor
+                .handle_log_notification(logs, &address)
+                .await
+                .unwrap();
+        }
+
+        assert!(matches!(
+            events.try_recv().unwrap(),
+            DetectionEvent::Created { .. }
+        ));
+        assert!(matches!(
+            events.try_recv().unwrap(),
+            DetectionEvent::Filtered { .. }
+        ));
+        assert_eq!(stored_records(&dir.path().join("pools.json")).len(), 1);
+    }
+
+    #[test]
+    fn the_pool_balances_are_found_among_many_others() {
+        let base = Pubkey::new_unique().to_string();
+        let mut balances: Vec<_> = (0..20_000)
+            .map(|n| pool_balance(&Pubkey::new_unique().to_string(), RAY_FEE, n))
+            .collect();
+        balances.insert(12_345, pool_balance(&base, LP_OWNER, 7));
+        balances.push(pool_balance(WSOL_MINT, LP_OWNER, 3));
+        let quote_mints = MonitorConfig::default().quote_mints;
+
+        // Only a borrow of the balances goes in; nothing is copied but the match.
+        let side = |is_quote| {
+            let info =
+                TokenMonitor::extract_token_info(&balances, is_quote, LP_OWNER, &quote_mints)
+                    .unwrap();
+            (info.address, info.amount)
+        };
+
+        assert_eq!(side(false), (base.clone(), 7));
+        assert_eq!(side(true), (WSOL_MINT.to_string(), 3));
+    }
+
+    #[tokio::test]
+    async fn risky_pools_score_high_and_are_dropped_above_the_threshold() {
+        let weights = RiskWeights::default();
+        let mut risky = pool_with_reserves(1_000_000, 1);
+        risky.mint_authority_set = Some(true);
+        risky.freeze_authority_set = Some(true);
+        risky.top_holder_pct = Some(90.0);
+        risky.fresh_mint = true;
+        // 30 + 30 + 20 + 10 + 10.
+        assert_eq!(weights.score(&risky), 100);
+
+        let mut safe = pool_with_reserves(1_000_000, 1);
+        safe.quote_info.lp_amount = Decimal::from(500);
+        safe.mint_authority_set = Some(false);
+        safe.freeze_authority_set = Some(false);
+        safe.top_holder_pct = Some(5.0);
+        assert_eq!(weights.score(&safe), 0);
+
+        let dir = tempfile::tempdir().unwrap();
+        let config = MonitorConfig {
+            max_risk_score: Some(15),
+            ..MonitorConfig::default()
+        };
+        let (monitor, mut events) = replay_monitor(dir.path(), config).await;
+        // A fresh mint with one quote token of liquidity scores 20.
+        let fresh = PoolFixture {
+            fresh_mint: true,
+            quote_amount: 1_000_000,
+            ..PoolFixture::new()
+        };
+
+        let signature = fresh.write(&dir.path().join("fixtures"));
+        let event = detect(&monitor, &mut events, signature).await;
+        assert!(
+            matches!(event, DetectionEvent::Filtered { .. }),
+            "{:?}",
+            event
+        );
+        let signature = PoolFixture::new().write(&dir.path().join("fixtures"));
+        assert_eq!(
+            created(detect(&monitor, &mut events, signature).await).risk_score,
+            0
+        );
+    }
+
+    #[test]
+    fn sampling_picks_the_same_signatures_on_every_run() {
+        let signatures: Vec<_> = (0..2_000).map(|_| Signature::new_unique()).collect();
+        let sample = |rate| -> Vec<_> {
+            signatures
+                .iter()
+                .filter(|signature| is_sampled(signature, rate))
+                .collect()
+        };
+
+        let tenth = sample(0.1);
+        assert_eq!(tenth, sample(0.1));
+        // Roughly one in ten, not exactly: it's a hash.
+        assert!((150..250).contains(&tenth.len()), "{}", tenth.len());
+        assert!(sample(0.0).is_empty());
+        assert_eq!(sample(1.0).len(), signatures.len());
+
+        // Pinned, so a change to the hash shows up as a different sample.
+        let pinned: Vec<u8> = (0..100)
+            .filter(|byte| is_sampled(&Signature::from([*byte; 64]), 0.1))
+            .collect();
+        assert_eq!(pinned, [14, 28, 34, 50, 53, 57, 67, 73, 86, 89, 96]);
+    }
+
+    #[test]
+    fn rolling_stats_average_the_counted_events_over_the_window() {
+        let stats = DetectionStats::default();
+        let mut window = VecDeque::new();
+
+        // Two 10s intervals: 30 pools and 5 errors, then 10 pools.
+        for _ in 0..30 {
+            stats.pools.fetch_add(1, Ordering::Relaxed);
+        }
+        stats.errors.fetch_add(5, Ordering::Relaxed);
+        stats.record_enrichment(Duration::from_millis(4));
+        stats.record_enrichment(Duration::from_millis(8));
+        window.push_back(stats.take_sample());
+        stats.pools.fetch_add(10, Ordering::Relaxed);
+        window.push_back(stats.take_sample());
+
+        assert_eq!(
+            RollingStats::over(&window, Duration::from_secs(10)),
+            RollingStats {
+                pools_per_sec: 2.0,
+                errors_per_sec: 0.25,
+                avg_enrichment_ms: 6.0,
+            }
+        );
+        // Taking a sample resets the counters.
+        assert_eq!(stats.take_sample().pools, 0);
+    }
+
+    /// Keeps every record in memory, or fails every write when `failing`.
+    #[derive(Default)]
+    struct MemoryStore {
+        records: Arc<Mutex<Vec<String>>>,
+        failing: bool,
+    }
+
+    impl Storage for MemoryStore {
+        fn name(&self) -> &str {
+            "memory"
+        }
+
+        fn store<'a>(
+            &'a self,
+            record: &'a str,
+            _date: NaiveDate,
+        ) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>> {
+            Box::pin(async move {
+                if self.failing {
+                    return Err(anyhow!("store unavailable"));
+                }
+                self.records.lock().unwrap().push(record.to_string());
+                Ok(())
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn every_store_receives_the_record_despite_a_failing_one() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut monitor, mut events) = replay_monitor(dir.path(), MonitorConfig::default()).await;
+        let (first, second) = (MemoryStore::default(), MemoryStore::default());
+        let (first_records, second_records) = (first.records.clone(), second.records.clone());
+        monitor.add_store(Box::new(first));
+        monitor.add_store(Box::new(MemoryStore {
+            failing: true,
+            ..MemoryStore::default()
+        }));
+        monitor.add_store(Box::new(second));
+
+        let signature = PoolFixture::new().write(&dir.path().join("fixtures"));
+        let token_data = created(detect(&monitor, &mut events, signature).await);
+
+        for records in [first_records, second_records] {
+            let records = records.lock().unwrap();
+            assert_eq!(records.len(), 1);
+            let stored: TokenData = serde_json::from_str(&records[0]).unwrap();
+            assert_eq!(stored.lp_signature, token_data.lp_signature);
+        }
+        assert_eq!(stored_records(&dir.path().join("pools.json")).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn threshold_strategy_buys_only_liquid_low_risk_sol_pools() {
+        let strategy = ThresholdStrategy {
+            sol_amount: Decimal::new(5, 1),
+            min_sol_liquidity: Decimal::from(10),
+            max_risk_score: 40,
+        };
+        let pool = |quote_mint: &str, sol: i64, risk_score| {
+            let mut data = pool_with_reserves(1_000_000, 0);
+            data.quote_info = TokenInfo::new(quote_mint.to_string(), 0, sol as u64);
+            data.risk_score = risk_score;
+            data
+        };
+        let skipped = |data| matches!(strategy.on_pool(&data), Decision::Skip { .. });
+
+        assert_eq!(
+            strategy.on_pool(&pool(WSOL_MINT, 50, 40)),
+            Decision::Buy {
+                sol_amount: Decimal::new(5, 1)
+            }
+        );
+        assert!(skipped(pool(USDC_MINT, 50, 0)));
+        assert!(skipped(pool(WSOL_MINT, 9, 0)));
+        assert!(skipped(pool(WSOL_MINT, 50, 41)));
+
+        // The decision follows the stored pool as an event.
+        let dir = tempfile::tempdir().unwrap();
+        let (mut monitor, mut events) = replay_monitor(dir.path(), MonitorConfig::default()).await;
+        monitor.set_strategy(Box::new(strategy.clone()));
+        let signature = PoolFixture::new().write(&dir.path().join("fixtures"));
+        monitor.handle_signature(&signature).await.unwrap();
+
+        match events.try_recv().unwrap() {
+            DetectionEvent::Decision {
+                signature: decided,
+                decision,
+            } => {
+                assert_eq!(decided, signature.to_string());
+                assert!(matches!(decision, Decision::Buy { .. }), "{:?}", decision);
+            }
+            other => panic!("expected a decision, got {:?}", other),
+        }
+        assert!(matches!(
+            events.try_recv().unwrap(),
+            DetectionEvent::Created { .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn each_timestamp_format_renders_the_same_instant() {
+        let instant = DateTime::from_timestamp(1_717_243_200, 123_000_000).unwrap();
+        let expected = [
+            (TimestampFormat::Rfc3339, "2024-06-01T12:00:00.123+00:00"),
+            (TimestampFormat::UnixSeconds, "1717243200"),
+            (TimestampFormat::UnixMillis, "1717243200123"),
+        ];
+
+        for (format, rendered) in expected {
+            assert_eq!(format.format(instant), rendered);
+
+            // Stored as configured, and read back as the same instant.
+            let dir = tempfile::tempdir().unwrap();
+            let config = MonitorConfig {
+                timestamp_format: format,
+                ..MonitorConfig::default()
+            };
+            let (mut monitor, mut events) = replay_monitor(dir.path(), config).await;
+            monitor.set_clock(Arc::new(MockClock::new(instant)));
+            let signature = PoolFixture::new().write(&dir.path().join("fixtures"));
+            let token_data = created(detect(&monitor, &mut events, signature).await);
+
+            assert_eq!(token_data.timestamp, rendered);
+            let precision = match format {
+                TimestampFormat::UnixSeconds => 1_000,
+                _ => 1,
+            };
+            let parsed = parse_timestamp(&token_data.timestamp).unwrap();
+            assert_eq!(
+                parsed.timestamp_millis(),
+                instant.timestamp_millis() / precision * precision
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn both_mints_are_verified_in_one_batched_call() {
+        let dir = tempfile::tempdir().unwrap();
+        let fixture = PoolFixture::new();
+        let signature = fixture.write(&dir.path().join("fixtures"));
+        let mint = Mint {
+            supply: 1_000_000,
+            decimals: 6,
+            is_initialized: true,
+            ..Mint::default()
+        };
+        let account = ui_account(&spl_token::id(), &packed_mint(mint));
+        let rpc = ScriptedRpc::default();
+        rpc.reply(
+            RpcRequest::GetMultipleAccounts,
+            serde_json::json!({ "context": { "slot": 1 }, "value": [account, account] }),
+        );
+        let config = MonitorConfig {
+            verify_mints: true,
+            ..MonitorConfig::default()
+        };
+        let (monitor, mut events) = replay_monitor_with(dir.path(), &rpc, config).await;
+
+        let data = created(detect(&monitor, &mut events, signature).await);
+
+        assert_eq!(data.mint_verified, Some(true));
+        assert_eq!(rpc.calls(), [RpcRequest::GetMultipleAccounts]);
+        let requested = &rpc.params(RpcRequest::GetMultipleAccounts)[0][0];
+        assert_eq!(
+            *requested,
+            serde_json::json!([fixture.base_mint, fixture.quote_mint])
+        );
+    }
+
+    #[tokio::test]
+    async fn a_transaction_missing_one_required_mention_is_filtered() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = MonitorConfig {
+            required_mentions: vec![AMM_V4_PROGRAM.to_string(), USDC_MINT.to_string()],
+            ..MonitorConfig::default()
+        };
+        let (monitor, mut events) = replay_monitor(dir.path(), config).await;
+
+        // Mentions the program but is quoted in WSOL.
+        let signature = PoolFixture::new().write(&dir.path().join("fixtures"));
+        match detect(&monitor, &mut events, signature).await {
+            DetectionEvent::Filtered { reason, .. } => {
+                assert_eq!(reason, format!("does not mention {}", USDC_MINT));
+            }
+            other => panic!("expected the pool to be filtered, got {:?}", other),
+        }
+
+        let usdc = PoolFixture {
+            quote_mint: USDC_MINT.to_string(),
+            ..PoolFixture::new()
+        };
+        let signature = usdc.write(&dir.path().join("fixtures"));
+        created(detect(&monitor, &mut events, signature).await);
+        assert_eq!(stored_records(&dir.path().join("pools.json")).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn a_missing_block_time_falls_back_to_the_clock_and_skips_latency() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = DateTime::from_timestamp(1_717_243_200, 0).unwrap();
+        let (mut monitor, mut events) = replay_monitor(dir.path(), MonitorConfig::default()).await;
+        monitor.set_clock(Arc::new(MockClock::new(now)));
+
+        for (block_time, estimated) in [(None, true), (Some(1_717_243_190), false)] {
+            let fixture = PoolFixture {
+                block_time,
+                ..PoolFixture::new()
+            };
+            let signature = fixture.write(&dir.path().join("fixtures"));
+            let event = detect(&monitor, &mut events, signature).await;
+            let DetectionEvent::Created {
+                token_data,
+                detection_latency_ms,
+            } = event
+            else {
+                panic!("expected a created pool, got {:?}", event);
+            };
+
+            assert_eq!(
+                token_data.block_time,
+                Some(block_time.unwrap_or(now.timestamp()))
+            );
+            assert_eq!(token_data.block_time_estimated, estimated);
+            assert_eq!(token_data.timestamp, now.to_rfc3339());
+            // A latency measured from the detection time itself would be zero.
+            assert_eq!(detection_latency_ms, block_time.map(|_| 10_000));
+        }
+    }
+
+    #[cfg(feature = "api")]
+    #[test]
+    fn feed_items_without_a_block_time_are_dated_by_detection() {
+        let legacy = stored_pool("legacy", "2024-06-01T12:00:00+00:00", WSOL_MINT);
+        assert_eq!(legacy.block_time, None);
+
+        let feed = api::render_feed(&[legacy]);
+
+        assert!(
+            feed.contains("<pubDate>Sat, 1 Jun 2024 12:00:00 +0000</pubDate>"),
+            "{}",
+            feed
+        );
+    }
+
+    #[cfg(feature = "sentry")]
+    #[test]
+    fn failed_signatures_are_reported_with_their_signature_and_skips_are_not() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = Signature::new_unique();
+        // Mentions no USDC, so it's filtered.
+        let skipped = PoolFixture::new().write(&dir.path().join("fixtures"));
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        let captured = sentry::test::with_captured_events(|| {
+            runtime.block_on(async {
+                let config = MonitorConfig {
+                    required_mentions: vec![USDC_MINT.to_string()],
+                    ..MonitorConfig::default()
+                };
+                let (monitor, mut events) = replay_monitor(dir.path(), config).await;
+
+                // No fixture, so the fetch fails.
+                let event = detect(&monitor, &mut events, missing).await;
+                assert!(
+                    matches!(event, DetectionEvent::Failed { .. }),
+                    "{:?}",
+                    event
+                );
+                let event = detect(&monitor, &mut events, skipped).await;
+                assert!(
+                    matches!(event, DetectionEvent::Filtered { .. }),
+                    "{:?}",
+                    event
+                );
+            })
+        });
+
+        assert_eq!(captured.len(), 1);
+        assert_eq!(
+            captured[0].tags.get("signature"),
+            Some(&missing.to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn a_fee_payer_other_than_the_initializer_is_kept_apart() {
+        for variant in [PoolVariant::AmmV4, PoolVariant::Cpmm] {
+            let dir = tempfile::tempdir().unwrap();
+            let config = MonitorConfig {
+                variant,
+                ..MonitorConfig::default()
+            };
+            let (monitor, mut events) = replay_monitor(dir.path(), config).await;
+            let fixture = PoolFixture {
+                variant,
+                ..PoolFixture::new()
+            };
+            assert_ne!(fixture.fee_payer, fixture.initializer);
+
+            let signature = fixture.write(&dir.path().join("fixtures"));
+            let data = created(detect(&monitor, &mut events, signature).await);
+
+            assert_eq!(data.fee_payer, fixture.fee_payer, "{:?}", variant);
+            assert_eq!(data.creator, fixture.fee_payer, "{:?}", variant);
+            assert_eq!(data.initializer, Some(fixture.initializer), "{:?}", variant);
+        }
+    }
+
+    #[tokio::test]
+    async fn repeat_signature_queries_within_the_ttl_share_one_call() {
+        let rpc = ScriptedRpc::default();
+        let client =
+            ReadOnlyRpcClient::new(rpc.client()).with_signatures_cache(Duration::from_millis(100));
+        let address = Pubkey::new_unique();
+        let query = |until| {
+            client.get_signatures_for_address_with_config(
+                &address,
+                GetConfirmedSignaturesForAddress2Config {
+                    until,
+                    ..Default::default()
+                },
+            )
+        };
+
+        query(None).await.unwrap();
+        query(None).await.unwrap();
+        assert_eq!(rpc.count(RpcRequest::GetSignaturesForAddress), 1);
+
+        // Another cursor is another query.
+        query(Some(Signature::new_unique())).await.unwrap();
+        assert_eq!(rpc.count(RpcRequest::GetSignaturesForAddress), 2);
+
+        sleep(Duration::from_millis(150)).await;
+        query(None).await.unwrap();
+        assert_eq!(rpc.count(RpcRequest::GetSignaturesForAddress), 3);
+    }
+
+    #[tokio::test]
+    async fn the_legacy_schema_stores_both_tokens_in_an_array() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = MonitorConfig {
+            output_schema: OutputSchema::Legacy,
+            ..MonitorConfig::default()
+        };
+        let (monitor, mut events) = replay_monitor(dir.path(), config).await;
+        let fixture = PoolFixture::new();
+        let signature = fixture.write(&dir.path().join("fixtures"));
+
+        created(detect(&monitor, &mut events, signature).await);
+
+        let stored = std::fs::read_to_string(dir.path().join("pools.json")).unwrap();
+        let record: Value = serde_json::from_str(stored.trim()).unwrap();
+        let keys: Vec<_> = record.as_object().unwrap().keys().cloned().collect();
+        assert_eq!(keys, ["creator", "lp_signature", "timestamp", "tokens"]);
+        assert_eq!(record["lp_signature"], signature.to_string());
+        assert_eq!(record["creator"], fixture.fee_payer);
+        let mints: Vec<_> = record["tokens"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|token| token["address"].clone())
+            .collect();
+        assert_eq!(mints, [fixture.base_mint, fixture.quote_mint]);
+        assert_eq!(record["tokens"][0]["amount"], fixture.base_amount);
+    }
+
+    #[tokio::test]
+    async fn enrichment_stops_once_the_per_event_rpc_cap_is_spent() {
+        let dir = tempfile::tempdir().unwrap();
+        let signature = PoolFixture::new().write(&dir.path().join("fixtures"));
+        let rpc = ScriptedRpc::default();
+        holders(&rpc, 10, 100);
+        let config = MonitorConfig {
+            verify_mints: true,
+            check_top_holder: true,
+            // The mint check and the largest accounts, but not the supply.
+            max_rpc_calls_per_event: Some(2),
+            ..MonitorConfig::default()
+        };
+        let (monitor, mut events) = replay_monitor_with(dir.path(), &rpc, config).await;
+
+        let data = created(detect(&monitor, &mut events, signature).await);
+
+        assert_eq!(
+            rpc.calls(),
+            [
+                RpcRequest::GetMultipleAccounts,
+                RpcRequest::GetTokenLargestAccounts
+            ]
+        );
+        assert!(data.mint_verified.is_some());
+        assert_eq!(data.top_holder_pct, None);
+        assert!(!data.enrichment_complete);
+    }
+
+    #[tokio::test]
+    async fn diagnostics_pass_against_a_working_setup_and_fail_against_a_broken_one() {
+        let dir = tempfile::tempdir().unwrap();
+        let (ws_url, _) = closing_pubsub_server().await;
+        let rpc = ScriptedRpc::default();
+        rpc.reply(RpcRequest::GetHealth, Value::from("ok")).reply(
+            RpcRequest::GetSignaturesForAddress,
+            serde_json::json!([{
+                "signature": Signature::new_unique().to_string(),
+                "slot": 1,
+                "err": null,
+                "blockTime": Utc::now().timestamp() - 10,
+            }]),
+        );
+        let working = AppConfig {
+            rpc_url: "http://unused".to_string(),
+            ws_url,
+            data_path: dir.path().join("pools.json"),
+            sentry_dsn: None,
+            monitor: MonitorConfig::default(),
+        };
+        let names = [
+            "RPC endpoint",
+            "Websocket endpoint",
+            "Output path",
+            "Watched address",
+        ];
+        let outcomes = |checks: Vec<DiagnosticCheck>| -> Vec<_> {
+            checks
+                .into_iter()
+                .map(|check| (check.name, check.outcome))
+                .collect()
+        };
+
+        let checks = diagnose_with(&ReadOnlyRpcClient::new(rpc.client()), &working).await;
+        let passed = outcomes(checks);
+        assert_eq!(
+            passed.iter().map(|(name, _)| *name).collect::<Vec<_>>(),
+            names
+        );
+        assert!(
+            passed.iter().all(|(_, outcome)| outcome.is_ok()),
+            "{:?}",
+            passed
+        );
+        // Checking the output path leaves nothing behind.
+        assert!(!working.data_path.exists());
+
+        let rpc = ScriptedRpc::default();
+        rpc.reply(RpcRequest::GetHealth, Value::from(5));
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let dead_ws_url = format!("ws://{}", listener.local_addr().unwrap());
+        drop(listener);
+        let broken = AppConfig {
+            ws_url: dead_ws_url,
+            data_path: dir.path().join("missing").join("pools.json"),
+            monitor: MonitorConfig {
+                ray_fee: "not-a-pubkey".to_string(),
+                ..MonitorConfig::default()
+            },
+            ..working
+        };
+
+        let checks = diagnose_with(&ReadOnlyRpcClient::new(rpc.client()), &broken).await;
+        let failed = outcomes(checks);
+        assert_eq!(
+            failed.iter().map(|(name, _)| *name).collect::<Vec<_>>(),
+            names
+        );
+        assert!(
+            failed.iter().all(|(_, outcome)| outcome.is_err()),
+            "{:?}",
+            failed
+        );
+        assert!(failed[3]
+            .1
+            .as_ref()
+            .unwrap_err()
+            .contains("'not-a-pubkey' is not a valid pubkey"));
+    }
+
+    /// A `Write` whose bytes can be read back through the other clones.
+    #[derive(Clone, Default)]
+    struct SharedOutput(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for SharedOutput {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            std::io::Write::write(&mut *self.0.lock().unwrap(), buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedOutput {
+        fn lines(&self) -> usize {
+            String::from_utf8(self.0.lock().unwrap().clone())
+                .unwrap()
+                .lines()
+                .count()
+        }
+    }
+
+    #[tokio::test]
+    async fn file_first_prints_nothing_for_a_record_a_store_failed_to_write() {
+        for (order, printed) in [(TeeOrder::FileFirst, 0), (TeeOrder::StdoutFirst, 1)] {
+            let dir = tempfile::tempdir().unwrap();
+            let config = MonitorConfig {
+                tee_stdout: Some(order),
+                ..MonitorConfig::default()
+            };
+            let (mut monitor, mut events) = replay_monitor(dir.path(), config).await;
+            let output = SharedOutput::default();
+            monitor.set_record_output(Box::new(output.clone()));
+            monitor.add_store(Box::new(MemoryStore {
+                failing: true,
+                ..MemoryStore::default()
+            }));
+            let signature = PoolFixture::new().write(&dir.path().join("fixtures"));
+
+            let event = detect(&monitor, &mut events, signature).await;
+
+            assert!(
+                matches!(event, DetectionEvent::Failed { .. }),
+                "{:?}",
+                event
+            );
+            assert_eq!(output.lines(), printed, "{:?}", order);
+        }
+
+        // With every store working, file-first prints the record after storing it.
+        let dir = tempfile::tempdir().unwrap();
+        let config = MonitorConfig {
+            tee_stdout: Some(TeeOrder::FileFirst),
+            ..MonitorConfig::default()
+        };
+        let (mut monitor, mut events) = replay_monitor(dir.path(), config).await;
+        let output = SharedOutput::default();
+        monitor.set_record_output(Box::new(output.clone()));
+        let signature = PoolFixture::new().write(&dir.path().join("fixtures"));
+        created(detect(&monitor, &mut events, signature).await);
+        assert_eq!(output.lines(), 1);
+        assert_eq!(stored_records(&dir.path().join("pools.json")).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn compute_units_and_fee_are_captured_in_every_meta_shape() {
+        let dir = tempfile::tempdir().unwrap();
+        let (monitor, mut events) = replay_monitor(dir.path(), MonitorConfig::default()).await;
+        let fixtures = dir.path().join("fixtures");
+
+        let known = PoolFixture {
+            compute_units: Some(123_456),
+            fee: 7_500,
+            ..PoolFixture::new()
+        };
+        let data = created(detect(&monitor, &mut events, known.write(&fixtures)).await);
+        assert_eq!(
+            (data.compute_units, data.fee_lamports),
+            (Some(123_456), 7_500)
+        );
+
+        let null = PoolFixture {
+            compute_units: None,
+            ..PoolFixture::new()
+        };
+        let data = created(detect(&monitor, &mut events, null.write(&fixtures)).await);
+        assert_eq!((data.compute_units, data.fee_lamports), (None, 5_000));
+
+        // Nodes that predate the field leave it out entirely.
+        let absent = PoolFixture::new();
+        let mut transaction = serde_json::to_value(absent.transaction()).unwrap();
+        let meta = transaction["meta"].as_object_mut().unwrap();
+        assert!(meta.remove("computeUnitsConsumed").is_some());
+        let path = fixture_path(&fixtures, &absent.signature);
+        std::fs::write(path, transaction.to_string()).unwrap();
+        let data = created(detect(&monitor, &mut events, absent.signature).await);
+        assert_eq!((data.compute_units, data.fee_lamports), (None, 5_000));
+    }
+
+    #[test]
+    fn all_balances_keeps_every_entry_sorted_and_skips_bad_amounts() {
+        let other = Pubkey::new_unique().to_string();
+        let mut unparseable = pool_balance(USDT_MINT, LP_OWNER, 0);
+        unparseable.ui_token_amount.amount = "not a number".to_string();
+        let balances = [
+            pool_balance(WSOL_MINT, LP_OWNER, 20),
+            pool_balance(&other, RAY_FEE, 7),
+            unparseable,
+            pool_balance(USDC_MINT, LP_OWNER, 5),
+            pool_balance(WSOL_MINT, RAY_FEE, 3),
+        ];
+
+        let infos = TokenMonitor::all_balances(&Signature::default(), &balances);
+
+        let mut expected = vec![
+            (USDC_MINT.to_string(), 5),
+            (WSOL_MINT.to_string(), 3),
+            (WSOL_MINT.to_string(), 20),
+            (other, 7),
+        ];
+        expected.sort();
+        let found: Vec<_> = infos
+            .into_iter()
+            .map(|info| (info.address, info.amount))
+            .collect();
+        assert_eq!(found, expected);
+    }
+
+    #[tokio::test]
+    async fn backfill_and_fetch_use_their_own_commitments() {
+        let dir = tempfile::tempdir().unwrap();
+        let fixture = PoolFixture::new();
+        let rpc = ScriptedRpc::default();
+        rpc.reply(
+            RpcRequest::GetSignaturesForAddress,
+            serde_json::json!([{ "signature": fixture.signature.to_string(), "slot": 2 }]),
+        )
+        .reply(
+            RpcRequest::GetTransaction,
+            serde_json::to_value(fixture.transaction()).unwrap(),
+        );
+        let config = MonitorConfig {
+            fetch_commitment: CommitmentConfig::confirmed(),
+            backfill_commitment: CommitmentConfig::finalized(),
+            ..MonitorConfig::default()
+        };
+        let monitor = mock_monitor(dir.path(), &rpc, config).await;
+
+        let newest = monitor
+            .backfill(&Pubkey::from_str(RAY_FEE).unwrap(), 1)
+            .await
+            .unwrap();
+
+        assert_eq!(newest, Some(2));
+        let commitments = |request| -> Vec<Value> {
+            rpc.params(request)
+                .iter()
+                .map(|params| params[1]["commitment"].clone())
+                .collect()
+        };
+        assert_eq!(
+            commitments(RpcRequest::GetSignaturesForAddress),
+            ["finalized"]
+        );
+        assert_eq!(commitments(RpcRequest::GetTransaction), ["confirmed"]);
+    }
+
+    #[tokio::test]
+    async fn a_pool_that_fails_to_store_is_not_a_duplicate_on_retry() {
+        let dir = tempfile::tempdir().unwrap();
+        let fixture = PoolFixture::new();
+        let rpc = fixture.rpc();
+        rpc.reply(
+            RpcRequest::GetTransaction,
+            serde_json::to_value(fixture.transaction()).unwrap(),
+        );
+        let monitor = mock_monitor(dir.path(), &rpc, MonitorConfig::default()).await;
+        // A directory where the data file should be makes the write fail.
+        let path = dir.path().join("pools.json");
+        std::fs::create_dir(&path).unwrap();
+
+        assert!(monitor.process_signature(&fixture.signature).await.is_err());
+        std::fs::remove_dir(&path).unwrap();
+        let event = monitor.process_signature(&fixture.signature).await.unwrap();
+
+        assert!(matches!(event, DetectionEvent::Created { .. }));
+        assert_eq!(stored_records(&path).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn a_filtered_pool_does_not_claim_its_dedup_key() {
+        // A fresh mint with one quote token of liquidity scores 20.
+        let risky = PoolFixture {
+            fresh_mint: true,
+            quote_amount: 1_000_000,
+            ..PoolFixture::new()
+        };
+        let same_mint = PoolFixture {
+            base_mint: risky.base_mint.clone(),
+            ..PoolFixture::new()
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let rpc = ScriptedRpc::default();
+        for fixture in [&risky, &same_mint] {
+            rpc.reply(
+                RpcRequest::GetTransaction,
+                serde_json::to_value(fixture.transaction()).unwrap(),
+            );
+        }
+        let config = MonitorConfig {
+            dedup_key: DedupKey::BaseMint,
+            max_risk_score: Some(15),
+            ..MonitorConfig::default()
+        };
+        let monitor = mock_monitor(dir.path(), &rpc, config).await;
+
+        let event = monitor.process_signature(&risky.signature).await.unwrap();
+        assert!(
+            matches!(event, DetectionEvent::Filtered { .. }),
+            "{:?}",
+            event
+        );
+        let event = monitor
+            .process_signature(&same_mint.signature)
+            .await
+            .unwrap();
+        assert!(
+            matches!(event, DetectionEvent::Created { .. }),
+            "{:?}",
+            event
+        );
+    }
+
+    #[tokio::test]
+    async fn all_balances_are_recorded_only_when_enabled() {
+        let dir = tempfile::tempdir().unwrap();
+        let fixture = PoolFixture::new();
+        let config = MonitorConfig {
+            include_all_balances: true,
+            ..MonitorConfig::default()
+        };
+        let monitor = mock_monitor(dir.path(), &fixture.rpc(), config).await;
+
+        let data = pool(monitor.parse_transaction(&fixture.signature).await.unwrap());
+
+        let found: Vec<_> = data
+            .all_balances
+            .iter()
+            .map(|info| info.address.as_str())
+            .collect();
+        assert_eq!(found, [fixture.base_mint.as_str(), WSOL_MINT]);
+
+        let monitor = mock_monitor(dir.path(), &fixture.rpc(), MonitorConfig::default()).await;
+        let data = pool(monitor.parse_transaction(&fixture.signature).await.unwrap());
+
+        assert!(data.all_balances.is_empty());
+        assert!(serde_json::to_value(&data)
+            .unwrap()
+            .get("all_balances")
+            .is_none());
+    }
+
+    /// A monitor writing under `dir`, errors included, that subscribes at `ws_url`.
+    async fn pubsub_monitor(
+        dir: &Path,
+        rpc: &ScriptedRpc,
+        ws_url: &str,
+        config: MonitorConfig,
+    ) -> TokenMonitor {
+        let config = MonitorConfig {
+            error_log_path: dir.join("errors.txt"),
+            ..config
+        };
+        TokenMonitor::with_rpc_client(rpc.client(), ws_url, dir.join("pools.json"), config)
+            .await
+            .unwrap()
+    }
+
+    /// A monitor writing under `dir` whose RPC calls go to `rpc`, subscribed
+    /// to a server that sends nothing.
+    async fn mock_monitor(dir: &Path, rpc: &ScriptedRpc, config: MonitorConfig) -> TokenMonitor {
+        let url = notifying_pubsub_server(Vec::new()).await;
+        pubsub_monitor(dir, rpc, &url, config).await
+    }
+
+    #[tokio::test]
+    async fn poll_source_feeds_new_signatures_through_the_pipeline() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = Signature::new_unique().to_string();
+        let fixture = PoolFixture::new();
+        let rpc = ScriptedRpc::default();
+        rpc.reply(
+            RpcRequest::GetSignaturesForAddress,
+            serde_json::json!([{ "signature": history, "slot": 1, "err": null }]),
+        )
+        .reply(
+            RpcRequest::GetSignaturesForAddress,
+            // Only what is newer than `until`, the primed signature.
+            serde_json::json!([
+                { "signature": fixture.signature.to_string(), "slot": 2, "err": null },
+            ]),
+        )
+        .reply(
+            RpcRequest::GetTransaction,
+            serde_json::to_value(fixture.transaction()).unwrap(),
+        );
+        let config = MonitorConfig {
+            source: SourceKind::Poll,
+            poll_interval: Duration::from_millis(10),
+            ..MonitorConfig::default()
+        };
+        let mut monitor = mock_monitor(dir.path(), &rpc, config).await;
+        let mut events = monitor.subscribe_events(16);
+
+        let cancel = CancellationToken::new();
+        let run = tokio::spawn(Arc::new(monitor).monitor_new_tokens(cancel.clone()));
+        let event = timeout(Duration::from_secs(10), events.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        cancel.cancel();
+        run.await.unwrap().unwrap();
+
+        // The primed page is history; the next poll's signature is handled.
+        let DetectionEvent::Created { token_data, .. } = event else {
+            panic!("expected a created pool, got {:?}", event);
+        };
+        assert_eq!(token_data.lp_signature, fixture.signature.to_string());
+    }
+
+    #[tokio::test]
+    async fn a_poll_pages_back_to_the_previous_newest_signature() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = Signature::new_unique().to_string();
+        let fixture = PoolFixture::new();
+        // A full page of failed transactions arrived since the last poll...
+        let burst: Vec<_> = (0..SIGNATURES_PAGE_LIMIT)
+            .map(|_| {
+                serde_json::json!({
+                    "signature": Signature::new_unique().to_string(),
+                    "slot": 3,
+                    "err": { "InstructionError": [0, "InvalidAccountData"] },
+                })
+            })
+            .collect();
+        let oldest_in_burst = burst.last().unwrap()["signature"].clone();
+        let rpc = ScriptedRpc::default();
+        rpc.reply(
+            RpcRequest::GetSignaturesForAddress,
+            serde_json::json!([{ "signature": history, "slot": 1, "err": null }]),
+        )
+        .reply(RpcRequest::GetSignaturesForAddress, Value::Array(burst))
+        // ...on top of the pool, which only the next page back holds.
+        .reply(
+            RpcRequest::GetSignaturesForAddress,
+            serde_json::json!([
+                { "signature": fixture.signature.to_string(), "slot": 2, "err": null },
+            ]),
+        )
+        .reply(
+            RpcRequest::GetTransaction,
+            serde_json::to_value(fixture.transaction()).unwrap(),
+        );
+        let config = MonitorConfig {
+            source: SourceKind::Poll,
+            poll_interval: Duration::from_millis(10),
+            ..MonitorConfig::default()
+        };
+        let mut monitor = mock_monitor(dir.path(), &rpc, config).await;
+        let mut events = monitor.subscribe_events(16);
+
+        let cancel = CancellationToken::new();
+        let run = tokio::spawn(Arc::new(monitor).monitor_new_tokens(cancel.clone()));
+        let event = timeout(Duration::from_secs(10), events.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        cancel.cancel();
+        run.await.unwrap().unwrap();
+
+        let DetectionEvent::Created { token_data, .. } = event else {
+            panic!("expected a created pool, got {:?}", event);
+        };
+        assert_eq!(token_data.lp_signature, fixture.signature.to_string());
+        let params = rpc.params(RpcRequest::GetSignaturesForAddress);
+        assert_eq!(params[1][1]["until"], history);
+        assert_eq!(params[2][1]["until"], history);
+        assert_eq!(params[2][1]["before"], oldest_in_burst);
+    }
+
+    fn instruction(program_id_index: u8, data: &[u8]) -> UiCompiledInstruction {
+        UiCompiledInstruction {
+            program_id_index,
+            accounts: vec![1, 2, 3, 4, 5],
+            data: bs58::encode(data).into_string(),
+            stack_height: None,
+        }
+    }
+
+    fn pool_keys(program: &str) -> Vec<String> {
+        let mut keys = vec![program.to_string()];
+        keys.extend((0..5).map(|_| Pubkey::new_unique().to_string()));
+        keys
+    }
+
+    #[test]
+    fn cpmm_pool_account_comes_from_initialize_only() {
+        let keys = pool_keys(CPMM_PROGRAM);
+        // A swap (or any other CPMM instruction) in the same transaction.
+        let swap = instruction(0, &[143, 190, 90, 218, 196, 30, 51, 222]);
+        let mut initialize_data = CPMM_INITIALIZE_DISCRIMINATOR.to_vec();
+        initialize_data.extend([0; 24]);
+        let initialize = instruction(0, &initialize_data);
+
+        let found = |instructions: &[UiCompiledInstruction]| {
+            pool_creation_account(PoolVariant::Cpmm, &keys, instructions, 3)
+        };
+
+        assert_eq!(found(std::slice::from_ref(&swap)), None);
+        assert_eq!(found(&[swap, initialize]), Some(keys[4].clone()));
+    }
+
+    #[test]
+    fn amm_v4_pool_account_comes_from_initialize2() {
+        let keys = pool_keys(AMM_V4_PROGRAM);
+        let swap = instruction(0, &[9, 0, 0]);
+        let initialize2 = instruction(0, &[AMM_V4_INITIALIZE2_TAG, 254, 0]);
+
+        assert_eq!(
+            pool_creation_account(PoolVariant::AmmV4, &keys, std::slice::from_ref(&swap), 4),
+            None
+        );
+        assert_eq!(
+            pool_creation_account(PoolVariant::AmmV4, &keys, &[swap, initialize2], 4),
+            Some(keys[5].clone())
+        );
+    }
+
+    #[test]
+    fn quote_symbol_labels_known_mints_and_falls_back_to_the_mint() {
+        let labels = MonitorConfig::default().quote_labels;
+        let unknown = Pubkey::new_unique().to_string();
+
+        assert_eq!(quote_symbol(&labels, WSOL_MINT), "SOL");
+        assert_eq!(quote_symbol(&labels, &unknown), unknown);
+    }
+
+    /// A pubsub server that accepts a subscription, sends a log notification
+    /// for each of `signatures` and then keeps the connection open.
+    async fn notifying_pubsub_server(signatures: Vec<Signature>) -> String {
+        use futures::SinkExt;
+        use tokio_tungstenite::tungstenite::Message;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+            let Some(Ok(Message::Text(request))) = socket.next().await else {
+                return;
+            };
+            let request: Value = serde_json::from_str(&request).unwrap();
+            let reply = serde_json::json!({ "jsonrpc": "2.0", "result": 1, "id": request["id"] });
+            socket.send(Message::Text(reply.to_string())).await.unwrap();
+            for signature in signatures {
+                let notification = serde_json::json!({
+                    "jsonrpc": "2.0",
+                    "method": "logsNotification",
+                    "params": {
+                        "result": {
+                            "context": { "slot": 1 },
+                            "value": { "signature": signature.to_string(), "err": null, "logs": [] },
+                        },
+                        "subscription": 1,
+                    },
+                });
+                socket
+                    .send(Message::Text(notification.to_string()))
+                    .await
+                    .unwrap();
+            }
+            while let Some(Ok(_)) = socket.next().await {}
+        });
+        url
+    }
+
+    /// The records written to `path`, in order.
+    fn stored_records(path: &Path) -> Vec<Value> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => serde_json::Deserializer::from_str(&text)
+                .into_iter()
+                .map(|record| record.unwrap())
+                .collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    fn pool(outcome: ParseOutcome) -> TokenData {
+        match outcome {
+            ParseOutcome::Pool(token_data) => *token_data,
+            ParseOutcome::Skipped(reason) => panic!("expected a pool, skipped: {}", reason),
+        }
+    }
+
+    /// RPC double that records every method called and answers from queued
+    /// replies, falling back to the mock sender's canned responses.
+    #[derive(Clone, Default)]
+    struct ScriptedRpc {
+        calls: Arc<Mutex<Vec<(RpcRequest, Value)>>>,
+        replies: Arc<Mutex<HashMap<RpcRequest, VecDeque<Value>>>>,
+    }
+
+    impl ScriptedRpc {
+        /// Queues `value` as the answer to the next `request` not yet answered.
+        fn reply(&self, request: RpcRequest, value: Value) -> &Self {
+            let mut replies = self.replies.lock().unwrap();
+            replies.entry(request).or_default().push_back(value);
+            self
+        }
+
+        /// Calls made, leaving out the `getVersion` the client makes on its own.
+        fn calls(&self) -> Vec<RpcRequest> {
+            let calls = self.calls.lock().unwrap();
+            calls
+                .iter()
+                .map(|(call, _)| *call)
+                .filter(|call| *call != RpcRequest::GetVersion)
+                .collect()
+        }
+
+        /// Params of every `request` call made, in order.
+        fn params(&self, request: RpcRequest) -> Vec<Value> {
+            let calls = self.calls.lock().unwrap();
+            calls
+                .iter()
+                .filter(|(call, _)| *call == request)
+                .map(|(_, params)| params.clone())
+                .collect()
+        }
+
+        fn count(&self, request: RpcRequest) -> usize {
+            self.calls()
+                .into_iter()
+                .filter(|call| *call == request)
+                .count()
+        }
+
+        fn client(&self) -> RpcClient {
+            RpcClient::new_sender(
+                ScriptedSender {
+                    rpc: self.clone(),
+                    fallback: RpcClient::new_mock("succeeds".to_string()),
+                },
+                Default::default(),
+            )
+        }
+    }
+
+    struct ScriptedSender {
+        rpc: ScriptedRpc,
+        fallback: RpcClient,
+    }
+
+    #[async_trait::async_trait]
+    impl RpcSender for ScriptedSender {
+        async fn send(&self, request: RpcRequest, params: Value) -> ClientResult<Value> {
+            self.rpc
+                .calls
+                .lock()
+                .unwrap()
+                .push((request, params.clone()));
+            let reply = self
+                .rpc
+                .replies
+                .lock()
+                .unwrap()
+                .get_mut(&request)
+                .and_then(VecDeque::pop_front);
+            match reply {
+                Some(value) => Ok(value),
+                None => self.fallback.send(request, params).await,
+            }
+        }
+
+        fn get_transport_stats(&self) -> RpcTransportStats {
+            RpcTransportStats::default()
+        }
+
+        fn url(&self) -> String {
+            "scripted".to_string()
+        }
+    }
+
+    #[tokio::test]
+    async fn pools_quoted_in_native_sol_take_the_quote_from_lamports() {
+        for variant in [PoolVariant::AmmV4, PoolVariant::Cpmm] {
+            let dir = tempfile::tempdir().unwrap();
+            let fixture = PoolFixture {
+                variant,
+                native_quote_lamports: Some(2_500_000_000),
+                ..PoolFixture::new()
+            };
+            let config = MonitorConfig {
+                variant,
+                ..MonitorConfig::default()
+            };
+            let monitor = mock_monitor(dir.path(), &fixture.rpc(), config).await;
+
+            let data = pool(monitor.parse_transaction(&fixture.signature).await.unwrap());
+
+            // The vault's rent-exempt reserve is left out.
+            assert_eq!(data.base_info.address, fixture.base_mint, "{:?}", variant);
+            assert_eq!(data.quote_info.address, WSOL_MINT);
+            assert_eq!(data.quote_info.decimals, SOL_DECIMALS);
+            assert_eq!(data.quote_info.lp_amount, Decimal::new(25, 1));
+        }
+    }
+
+    #[tokio::test]
+    async fn a_pool_resolving_to_one_mint_on_both_sides_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        // Without WSOL as a quote mint, a WSOL balance reads as the base, while
+        // the lamports fallback also resolves the quote to WSOL.
+        let fixture = PoolFixture {
+            base_mint: WSOL_MINT.to_string(),
+            native_quote_lamports: Some(1_000_000_000),
+            ..PoolFixture::new()
+        };
+        let config = MonitorConfig {
+            quote_mints: vec![USDC_MINT.to_string()],
+            ..MonitorConfig::default()
+        };
+        let monitor = mock_monitor(dir.path(), &fixture.rpc(), config).await;
+
+        let Err(err) = monitor.parse_transaction(&fixture.signature).await else {
+            panic!("expected the pool to be rejected");
+        };
+
+        assert!(
+            matches!(err.downcast_ref(), Some(MonitorError::SameMint(mint)) if mint == WSOL_MINT),
+            "{}",
+            err
+        );
+    }
+
+    fn statuses(status: Value) -> Value {
+        serde_json::json!({ "context": { "slot": 1 }, "value": [status] })
+    }
+
+    #[tokio::test]
+    async fn the_fetch_waits_for_the_signature_status() {
+        let dir = tempfile::tempdir().unwrap();
+        let fixture = PoolFixture::new();
+        let rpc = ScriptedRpc::default();
+        rpc.reply(RpcRequest::GetSignatureStatuses, statuses(Value::Null))
+            .reply(
+                RpcRequest::GetTransaction,
+                serde_json::to_value(fixture.transaction()).unwrap(),
+            );
+        let config = MonitorConfig {
+            await_signature_status: true,
+            ..MonitorConfig::default()
+        };
+        let monitor = mock_monitor(dir.path(), &rpc, config).await;
+
+        let outcome = monitor.parse_transaction(&fixture.signature).await.unwrap();
+
+        assert!(matches!(outcome, ParseOutcome::Pool(_)));
+        // Not found, then finalized; only then is the transaction fetched.
+        assert_eq!(
+            rpc.calls(),
+            [
+                RpcRequest::GetSignatureStatuses,
+                RpcRequest::GetSignatureStatuses,
+                RpcRequest::GetTransaction
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn cancelling_returns_promptly_with_the_pool_stored() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pools.json");
+        let fixture = PoolFixture::new();
+        let url = notifying_pubsub_server(vec![fixture.signature]).await;
+        let monitor =
+            pubsub_monitor(dir.path(), &fixture.rpc(), &url, MonitorConfig::default()).await;
+
+        let cancel = CancellationToken::new();
+        let run = tokio::spawn(Arc::new(monitor).monitor_new_tokens(cancel.clone()));
+        timeout(Duration::from_secs(10), async {
+            while stored_records(&path).is_empty() {
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        cancel.cancel();
+
+        timeout(Duration::from_secs(1), run)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        let records = stored_records(&path);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0]["lp_signature"], fixture.signature.to_string());
+    }
+
+    #[tokio::test]
+    async fn a_full_queue_holds_notifications_back_instead_of_dropping_them() {
+        let dir = tempfile::tempdir().unwrap();
+        let signatures: Vec<_> = (0..4).map(|_| Signature::new_unique()).collect();
+        let url = notifying_pubsub_server(signatures.clone()).await;
+        // Holds the processor on the first signature while the rest arrive.
+        let rpc = ScriptedRpc::default();
+        rpc.reply(RpcRequest::GetSignatureStatuses, statuses(Value::Null));
+        let config = MonitorConfig {
+            await_signature_status: true,
+            queue_capacity: 1,
+            workers: 1,
+            ..MonitorConfig::default()
+        };
+        let monitor = Arc::new(pubsub_monitor(dir.path(), &rpc, &url, config).await);
+
+        let cancel = CancellationToken::new();
+        let run = tokio::spawn({
+            let (monitor, cancel) = (monitor.clone(), cancel.clone());
+            async move { monitor.monitor_new_tokens(cancel).await }
+        });
+        // One retried status poll, then one for every signature.
+        timeout(Duration::from_secs(5), async {
+            while rpc.count(RpcRequest::GetSignatureStatuses) < signatures.len() + 1 {
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        cancel.cancel();
+        timeout(Duration::from_secs(1), run)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+
+        let polled: HashSet<String> = rpc
+            .params(RpcRequest::GetSignatureStatuses)
+            .iter()
+            .map(|params| params[0][0].as_str().unwrap().to_string())
+            .collect();
+        let expected: HashSet<String> = signatures.iter().map(ToString::to_string).collect();
+        assert_eq!(polled, expected);
+    }
+
+    #[tokio::test]
+    async fn workers_never_exceed_the_pool_size() {
+        let (sender, receiver) = mpsc::channel(64);
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let done = Arc::new(AtomicUsize::new(0));
+
+        let mut workers = spawn_workers(3, receiver, {
+            let (running, peak, done) = (running.clone(), peak.clone(), done.clone());
+            move |_: u32| {
+                let (running, peak, done) = (running.clone(), peak.clone(), done.clone());
+                async move {
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    sleep(Duration::from_millis(5)).await;
+                    running.fetch_sub(1, Ordering::SeqCst);
+                    done.fetch_add(1, Ordering::SeqCst);
+                }
+            }
+        });
+        for item in 0..30 {
+            sender.send(item).await.unwrap();
+        }
+        drop(sender);
+        while workers.join_next().await.is_some() {}
+
+        assert_eq!(done.load(Ordering::SeqCst), 30);
+        assert_eq!(peak.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn slow_workers_still_let_the_queue_fill_before_blocking() {
+        let (sender, receiver) = mpsc::channel(4);
+        // Holds every worker until released.
+        let gate = Arc::new(tokio::sync::Semaphore::new(0));
+        let done = Arc::new(AtomicUsize::new(0));
+
+        let mut workers = spawn_workers(2, receiver, {
+            let (gate, done) = (gate.clone(), done.clone());
+            move |_: u32| {
+                let (gate, done) = (gate.clone(), done.clone());
+                async move {
+                    gate.acquire().await.unwrap().forget();
+                    done.fetch_add(1, Ordering::SeqCst);
+                }
+            }
+        });
+
+        // Two taken by the stuck workers, four waiting in the queue.
+        for item in 0..6 {
+            timeout(Duration::from_secs(1), sender.send(item))
+                .await
+                .unwrap()
+                .unwrap();
+        }
+        assert!(matches!(
+            sender.try_send(6),
+            Err(mpsc::error::TrySendError::Full(6))
+        ));
+        assert_eq!(done.load(Ordering::SeqCst), 0);
+
+        gate.add_permits(7);
+        sender.send(6).await.unwrap();
+        drop(sender);
+        while workers.join_next().await.is_some() {}
+        assert_eq!(done.load(Ordering::SeqCst), 7);
+    }
+
+    #[tokio::test]
+    async fn every_signature_ends_in_a_detection_event() {
+        let dir = tempfile::tempdir().unwrap();
+        let fixture = PoolFixture::new();
+        let mut failed_transaction = serde_json::to_value(fixture.transaction()).unwrap();
+        failed_transaction["meta"]["err"] =
+            serde_json::json!({ "InstructionError": [0, "InvalidArgument"] });
+        let rpc = fixture.rpc();
+        rpc.reply(RpcRequest::GetTransaction, failed_transaction)
+            .reply(RpcRequest::GetTransaction, Value::Null);
+        let mut monitor = mock_monitor(dir.path(), &rpc, MonitorConfig::default()).await;
+        let mut events = monitor.subscribe_events(4);
+        let signatures = [
+            fixture.signature,
+            Signature::new_unique(),
+            Signature::new_unique(),
+        ];
+
+        for signature in signatures {
+            let logs = serde_json::json!({ "signature": signature.to_string() });
+            let _ = monitor
+                .handle_log_notification(logs, &Pubkey::new_unique())
+                .await;
+        }
+
+        match events.try_recv().unwrap() {
+            DetectionEvent::Created { token_data, .. } => {
+                assert_eq!(token_data.lp_signature, fixture.signature.to_string())
+            }
+            other => panic!("expected a created pool, got {:?}", other),
+        }
+        match events.try_recv().unwrap() {
+            DetectionEvent::Filtered { signature, reason } => {
+                assert_eq!(signature, signatures[1].to_string());
+                assert_eq!(reason, "transaction failed");
+            }
+            other => panic!("expected a filtered signature, got {:?}", other),
+        }
+        assert!(matches!(
+            events.try_recv().unwrap(),
+            DetectionEvent::Failed { .. }
+        ));
+        assert_eq!(stored_records(&dir.path().join("pools.json")).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn pools_below_min_liquidity_are_filtered_with_the_reason() {
+        let dir = tempfile::tempdir().unwrap();
+        // 50,000 WSOL at the fixture's 6 decimals.
+        let fixture = PoolFixture::new();
+        let config = MonitorConfig {
+            min_liquidity: Some(Decimal::from(100_000)),
+            ..MonitorConfig::default()
+        };
+        let monitor = mock_monitor(dir.path(), &fixture.rpc(), config).await;
+
+        let event = monitor.process_signature(&fixture.signature).await.unwrap();
+
+        let DetectionEvent::Filtered {
+            signature: filtered,
+            reason,
+        } = event
+        else {
+            panic!("expected a filtered event, got {:?}", event);
+        };
+        assert_eq!(filtered, fixture.signature.to_string());
+        assert_eq!(reason, "50000.000000 SOL liquidity, below 100000");
+        assert!(stored_records(&dir.path().join("pools.json")).is_empty());
+    }
+
+    #[tokio::test]
+    async fn the_configured_fee_address_is_the_one_watched() {
+        let prefix = "RAY_FEE_TEST_";
+        let watched = Pubkey::new_unique().to_string();
+        env::set_var(format!("{}RAY_FEE", prefix), &watched);
+        env::set_var(format!("{}SOURCE", prefix), "poll");
+        let config = AppConfig::from_env(prefix).unwrap();
+        assert!(config.validate().is_empty(), "{:?}", config.validate());
+
+        let dir = tempfile::tempdir().unwrap();
+        let rpc = ScriptedRpc::default();
+        let monitor = mock_monitor(dir.path(), &rpc, config.monitor).await;
+        let cancel = CancellationToken::new();
+        let run = tokio::spawn(Arc::new(monitor).monitor_new_tokens(cancel.clone()));
+        while rpc.count(RpcRequest::GetSignaturesForAddress) == 0 {
+            sleep(Duration::from_millis(5)).await;
+        }
+        cancel.cancel();
+        run.await.unwrap().unwrap();
+
+        assert_eq!(
+            rpc.params(RpcRequest::GetSignaturesForAddress)[0][0],
+            watched.as_str()
+        );
+    }
+
+    #[tokio::test]
+    async fn cached_top_holder_pct_expires_when_the_clock_passes_the_ttl() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = MonitorConfig {
+            account_cache_ttl: Duration::from_secs(30),
+            ..MonitorConfig::default()
+        };
+        let mut monitor = mock_monitor(dir.path(), &ScriptedRpc::default(), config).await;
+        let clock = Arc::new(MockClock::new(Utc::now()));
+        monitor.set_clock(clock.clone());
+        let mint = Pubkey::new_unique().to_string();
+
+        monitor.cache_top_holder_pct(&mint, Some(12.5));
+        clock.advance(chrono::Duration::seconds(29));
+        assert_eq!(monitor.cached_top_holder_pct(&mint), Some(Some(12.5)));
+
+        clock.advance(chrono::Duration::seconds(1));
+        assert_eq!(monitor.cached_top_holder_pct(&mint), None);
+    }
+}
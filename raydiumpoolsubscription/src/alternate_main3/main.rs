@@ -0,0 +1,183 @@
+mod cli;
+
+#[cfg(feature = "api")]
+use davids_sling::api;
+#[cfg(feature = "protobuf")]
+use davids_sling::storage;
+use {
+    anyhow::{anyhow, Result},
+    cli::{
+        apply_color_choice, cancel_on_ctrl_c, config_errors, exit_on_errors, parse_cli,
+        print_diagnostics, print_token_data, resolve_config, ColorChoice,
+    },
+    davids_sling::{
+        config::DEFAULT_ENV_PREFIX,
+        diagnose,
+        event_log::{for_each_logged_event, EventCursor},
+        run_token_monitor,
+        storage::{diff_runs, repair_records, tail_records},
+        TokenMonitor,
+    },
+    std::{
+        env,
+        path::{Path, PathBuf},
+    },
+};
+
+/// Where `serve` listens unless told otherwise.
+#[cfg(feature = "api")]
+const DEFAULT_API_ADDR: &str = "127.0.0.1:8080";
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = parse_cli(env::args().skip(1))?;
+    apply_color_choice(cli.color.unwrap_or(ColorChoice::Auto));
+
+    let env_prefix = cli.env_prefix.as_deref().unwrap_or(DEFAULT_ENV_PREFIX);
+    let config = resolve_config(&cli);
+
+    if cli.print_config {
+        println!("{}", serde_json::to_string_pretty(&config?.redacted())?);
+        return Ok(());
+    }
+
+    match cli.command.as_deref() {
+        None => {}
+        Some("validate-config") => {
+            exit_on_errors(&config_errors(&config));
+            println!("OK");
+            return Ok(());
+        }
+        #[cfg(feature = "protobuf")]
+        Some("export-protobuf") => {
+            let [input, output] = cli.args.as_slice() else {
+                return Err(anyhow!("Usage: export-protobuf <input.jsonl> <output.pb>"));
+            };
+            let count = storage::export_protobuf(Path::new(input), Path::new(output))?;
+            println!("Exported {} records to {}", count, output);
+            return Ok(());
+        }
+        // `serve [addr]` answers `GET /pools` from the data file.
+        #[cfg(feature = "api")]
+        Some("serve") => {
+            let addr = match cli.args.first() {
+                Some(addr) => addr.parse()?,
+                None => DEFAULT_API_ADDR.parse()?,
+            };
+            return api::serve(addr, config?.data_path).await;
+        }
+        // `wal-pending <cursor>` prints unacknowledged events as JSON lines;
+        // `wal-ack <cursor> <offset>` records that they were delivered.
+        Some("wal-pending") => {
+            let [cursor] = cli.args.as_slice() else {
+                return Err(anyhow!("Usage: wal-pending <cursor>"));
+            };
+            let wal_path = config?
+                .monitor
+                .wal_path
+                .ok_or_else(|| anyhow!("wal-pending needs {}WAL_PATH", env_prefix))?;
+            let acked = EventCursor::new(PathBuf::from(cursor)).acked()?;
+            for_each_logged_event(&wal_path, acked, |logged| {
+                println!("{}", serde_json::to_string(&logged)?);
+                Ok(())
+            })
+            .await?;
+            return Ok(());
+        }
+        Some("wal-ack") => {
+            let [cursor, offset] = cli.args.as_slice() else {
+                return Err(anyhow!("Usage: wal-ack <cursor> <offset>"));
+            };
+            EventCursor::new(PathBuf::from(cursor)).ack(offset.parse()?)?;
+            return Ok(());
+        }
+        Some("tail") => {
+            let path = match cli.args.first() {
+                Some(path) => PathBuf::from(path),
+                None => config?.data_path,
+            };
+            return tail_records(&path, &cancel_on_ctrl_c(), |data| print_token_data(&data)).await;
+        }
+        Some("diagnose") => {
+            let checks = diagnose(&config?).await;
+            print_diagnostics(&checks);
+            if checks.iter().any(|check| check.outcome.is_err()) {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        // `repair [path]` drops malformed lines from the data file.
+        Some("diff") => {
+            let [a, b] = cli.args.as_slice() else {
+                return Err(anyhow!("Usage: diff <a.jsonl> <b.jsonl>"));
+            };
+            print!("{}", diff_runs(Path::new(a), Path::new(b))?);
+            return Ok(());
+        }
+        // `reparse <in> <out>` regenerates records from the transactions
+        // recorded with RECORD_DIR, read back from REPLAY_DIR.
+        Some("reparse") => {
+            let [input, output] = cli.args.as_slice() else {
+                return Err(anyhow!("Usage: reparse <input.jsonl> <output.jsonl>"));
+            };
+            let config = config?;
+            let monitor = TokenMonitor::new(
+                &config.rpc_url,
+                &config.ws_url,
+                config.data_path,
+                config.monitor,
+            )
+            .await?;
+            let report = monitor.reparse(Path::new(input), Path::new(output)).await?;
+            println!(
+                "Reparsed {} records, passed {} through to {}",
+                report.reparsed, report.passed_through, output
+            );
+            return Ok(());
+        }
+        Some("repair") => {
+            let path = match cli.args.first() {
+                Some(path) => PathBuf::from(path),
+                None => config?.data_path,
+            };
+            let report = repair_records(&path)?;
+            for line in &report.dropped {
+                eprintln!("Dropped malformed line {}", line);
+            }
+            println!(
+                "Kept {} records, dropped {} lines from {}",
+                report.kept,
+                report.dropped.len(),
+                path.display()
+            );
+            return Ok(());
+        }
+        Some(other) => return Err(anyhow!("Unknown command '{}'", other)),
+    }
+
+    let config = config?;
+    exit_on_errors(&config.validate());
+
+    // Reports are flushed when the guard drops at the end of `main`.
+    #[cfg(feature = "sentry")]
+    let _sentry = config.sentry_dsn.as_deref().map(|dsn| {
+        sentry::init((
+            dsn,
+            sentry::ClientOptions {
+                release: sentry::release_name!(),
+                ..Default::default()
+            },
+        ))
+    });
+
+    let cancel = cancel_on_ctrl_c();
+
+    run_token_monitor(
+        &config.rpc_url,
+        &config.ws_url,
+        config.data_path,
+        config.monitor,
+        cancel,
+    )
+    .await
+}
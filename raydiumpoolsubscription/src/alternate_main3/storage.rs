@@ -0,0 +1,507 @@
+use {
+    super::{config::MonitorConfig, read_token_data, SchemaMode, TeeOrder, TokenData},
+    anyhow::{anyhow, Result},
+    chrono::NaiveDate,
+    flate2::{read::GzDecoder, write::GzEncoder, Compression},
+    log::{info, warn},
+    serde_json::Value,
+    std::{
+        collections::BTreeMap,
+        fmt,
+        future::Future,
+        io::{BufRead, BufReader},
+        path::{Path, PathBuf},
+        pin::Pin,
+        sync::Mutex,
+    },
+    tokio::{
+        fs::OpenOptions,
+        io::AsyncWriteExt,
+        time::{sleep, Duration},
+    },
+    tokio_util::sync::CancellationToken,
+};
+
+/// How often `tail` checks the followed file for new lines or rotation.
+const TAIL_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Somewhere detections are written. Each record arrives as one compact JSON
+/// object, along with the UTC day it belongs to.
+pub trait Storage: Send + Sync {
+    /// Used in log messages.
+    fn name(&self) -> &str;
+
+    fn store<'a>(
+        &'a self,
+        record: &'a str,
+        date: NaiveDate,
+    ) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>>;
+}
+
+/// The JSON-lines file at `data_path`, or one file per day next to it with
+/// `partition_by_date`.
+pub struct JsonLinesStorage {
+    data_path: PathBuf,
+    partition_by_date: bool,
+    compress_rotated: bool,
+    /// `fsync` each record, so `TeeOrder::FileFirst` only prints durable ones.
+    sync: bool,
+    /// Partition file most recently written to.
+    current_partition: Mutex<Option<PathBuf>>,
+    /// Serializes writes to the data files.
+    write_lock: tokio::sync::Mutex<()>,
+}
+
+impl JsonLinesStorage {
+    pub fn new(data_path: PathBuf, config: &MonitorConfig) -> Self {
+        Self {
+            data_path,
+            partition_by_date: config.partition_by_date,
+            compress_rotated: config.compress_rotated,
+            sync: config.tee_stdout == Some(TeeOrder::FileFirst),
+            current_partition: Mutex::new(None),
+            write_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    async fn append(&self, record: &str, date: NaiveDate) -> Result<()> {
+        let path = if self.partition_by_date {
+            partition_path(&self.data_path, date)
+        } else {
+            self.data_path.clone()
+        };
+
+        // Held for the whole open/write/flush so concurrent detections each land
+        // as one intact line.
+        let _write_guard = self.write_lock.lock().await;
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await?;
+
+        let mut line = record.as_bytes().to_vec();
+        line.push(b'\n');
+        file.write_all(&line).await?;
+        // tokio's File buffers internally; flush so nothing is lost if we exit right after.
+        file.flush().await?;
+        if self.sync {
+            file.sync_data().await?;
+        }
+
+        if self.partition_by_date {
+            self.rotate_partition(path).await?;
+        }
+        Ok(())
+    }
+
+    /// Remembers the partition just written to. When that changes, the previous
+    /// partition is finished, so it gets compressed if `compress_rotated` is set.
+    async fn rotate_partition(&self, path: PathBuf) -> Result<()> {
+        let previous = {
+            let mut current = self.current_partition.lock().unwrap();
+            if current.as_ref() == Some(&path) {
+                return Ok(());
+            }
+            current.replace(path)
+        };
+
+        if let (Some(previous), true) = (previous, self.compress_rotated) {
+            tokio::task::spawn_blocking(move || gzip_file(&previous)).await??;
+        }
+        Ok(())
+    }
+}
+
+impl Storage for JsonLinesStorage {
+    fn name(&self) -> &str {
+        "JSON-lines file"
+    }
+
+    fn store<'a>(
+        &'a self,
+        record: &'a str,
+        date: NaiveDate,
+    ) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>> {
+        Box::pin(self.append(record, date))
+    }
+}
+
+/// Differences between two collection runs, keyed by `lp_signature`.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct DiffReport {
+    /// Only in the second run.
+    pub new: Vec<String>,
+    /// Only in the first run.
+    pub removed: Vec<String>,
+    /// In both runs with different contents.
+    pub changed: Vec<String>,
+}
+
+impl fmt::Display for DiffReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} new, {} removed, {} changed",
+            self.new.len(),
+            self.removed.len(),
+            self.changed.len()
+        )?;
+        for signature in &self.new {
+            writeln!(f, "+ {}", signature)?;
+        }
+        for signature in &self.removed {
+            writeln!(f, "- {}", signature)?;
+        }
+        for signature in &self.changed {
+            writeln!(f, "~ {}", signature)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compares two JSON-lines outputs. Records are compared as raw JSON, so a field
+/// the reader doesn't know about still counts as a change.
+pub fn diff_runs(a: &Path, b: &Path) -> Result<DiffReport> {
+    let before = load_records(a)?;
+    let after = load_records(b)?;
+
+    let mut report = DiffReport::default();
+    for (signature, record) in &after {
+        match before.get(signature) {
+            None => report.new.push(signature.clone()),
+            Some(previous) if previous != record => report.changed.push(signature.clone()),
+            Some(_) => {}
+        }
+    }
+    report.removed = before
+        .keys()
+        .filter(|signature| !after.contains_key(*signature))
+        .cloned()
+        .collect();
+
+    Ok(report)
+}
+
+/// Opens a JSON-lines file for reading, decompressing it if it ends in `.gz`.
+pub fn open_records(path: &Path) -> Result<Box<dyn BufRead>> {
+    let file = std::fs::File::open(path)?;
+    if path.extension().and_then(|ext| ext.to_str()) == Some("gz") {
+        Ok(Box::new(BufReader::new(GzDecoder::new(file))))
+    } else {
+        Ok(Box::new(BufReader::new(file)))
+    }
+}
+
+/// Compresses `path` to `<path>.gz` and removes the original.
+fn gzip_file(path: &Path) -> Result<()> {
+    let mut compressed_name = path.as_os_str().to_owned();
+    compressed_name.push(".gz");
+    let compressed_path = PathBuf::from(compressed_name);
+
+    let mut input = std::fs::File::open(path)?;
+    let mut encoder = GzEncoder::new(
+        std::fs::File::create(&compressed_path)?,
+        Compression::default(),
+    );
+    std::io::copy(&mut input, &mut encoder)?;
+    encoder.finish()?;
+
+    std::fs::remove_file(path)?;
+    Ok(())
+}
+
+pub fn load_records(path: &Path) -> Result<BTreeMap<String, Value>> {
+    let mut records = BTreeMap::new();
+
+    for (index, line) in open_records(path)?.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let record: Value = serde_json::from_str(&line)
+            .map_err(|err| anyhow!("{}:{}: {}", path.display(), index + 1, err))?;
+        let signature = record["lp_signature"]
+            .as_str()
+            .ok_or_else(|| anyhow!("{}:{}: missing lp_signature", path.display(), index + 1))?
+            .to_string();
+        records.insert(signature, record);
+    }
+
+    Ok(records)
+}
+
+/// `data/pools.jsonl` + 2024-06-01 -> `data/pools-2024-06-01.jsonl`.
+fn partition_path(base: &Path, date: NaiveDate) -> PathBuf {
+    let stem = base
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("pools");
+    let file_name = match base.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => format!("{}-{}.{}", stem, date.format("%Y-%m-%d"), ext),
+        None => format!("{}-{}", stem, date.format("%Y-%m-%d")),
+    };
+
+    base.with_file_name(file_name)
+}
+
+/// Converts a JSON-lines file (gzipped or not) into a length-delimited
+/// Protobuf file, returning how many records were written.
+#[cfg(feature = "protobuf")]
+pub fn export_protobuf(input: &Path, output: &Path) -> Result<usize> {
+    let mut store = super::protobuf::ProtobufStore::create(output)?;
+    let mut count = 0;
+
+    for line in open_records(input)?.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        store.append(&read_token_data(&line, SchemaMode::Lenient)?)?;
+        count += 1;
+    }
+    store.flush()?;
+
+    // Read the file back so a schema mismatch fails the export, not the consumer.
+    let exported = super::protobuf::read_protobuf_records(output)?;
+    if exported.len() != count {
+        return Err(anyhow!(
+            "Wrote {} records but read back {}",
+            count,
+            exported.len()
+        ));
+    }
+
+    Ok(count)
+}
+
+/// Lines `repair_records` kept and dropped.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct RepairReport {
+    pub kept: usize,
+    /// 1-based numbers of the lines that weren't a JSON object.
+    pub dropped: Vec<usize>,
+}
+
+/// Rewrites the JSON-lines file at `path` without the lines that aren't a
+/// complete JSON object, typically a last line cut short by a crash. The clean
+/// copy replaces the file by rename, so a failed repair leaves it untouched.
+/// Run it while nothing is writing to the file.
+pub fn repair_records(path: &Path) -> Result<RepairReport> {
+    if path.extension().and_then(|ext| ext.to_str()) == Some("gz") {
+        return Err(anyhow!(
+            "{} is compressed; repair the file before gzipping it",
+            path.display()
+        ));
+    }
+
+    let contents = std::fs::read(path)?;
+    let mut report = RepairReport::default();
+    let mut clean = Vec::with_capacity(contents.len());
+
+    for (index, line) in contents.split(|&byte| byte == b'\n').enumerate() {
+        if line.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+        let is_record = std::str::from_utf8(line)
+            .ok()
+            .and_then(|line| serde_json::from_str::<Value>(line).ok())
+            .is_some_and(|value| value.is_object());
+        if is_record {
+            clean.extend_from_slice(line);
+            clean.push(b'\n');
+            report.kept += 1;
+        } else {
+            report.dropped.push(index + 1);
+        }
+    }
+
+    if !report.dropped.is_empty() {
+        let mut temp_name = path.as_os_str().to_owned();
+        temp_name.push(".tmp");
+        let temp_path = PathBuf::from(temp_name);
+
+        std::fs::write(&temp_path, clean)?;
+        std::fs::rename(&temp_path, path)?;
+    }
+    Ok(report)
+}
+
+/// Follows `path` like `tail -f`, passing each `TokenData` appended to it to
+/// `on_record`. Starts at the current end of the file and reopens it from the
+/// start when it is replaced (new inode) or truncated.
+pub async fn tail_records(
+    path: &Path,
+    cancel: &CancellationToken,
+    mut on_record: impl FnMut(TokenData),
+) -> Result<()> {
+    use std::os::unix::fs::MetadataExt;
+    use tokio::io::{AsyncBufReadExt, AsyncSeekExt};
+
+    let open = |from_end: bool| async move {
+        let mut file = tokio::fs::File::open(path).await?;
+        let inode = file.metadata().await?.ino();
+        if from_end {
+            file.seek(std::io::SeekFrom::End(0)).await?;
+        }
+        Ok::<_, anyhow::Error>((tokio::io::BufReader::new(file), inode))
+    };
+
+    let (mut reader, mut inode) = open(true).await?;
+    let mut position = reader.stream_position().await?;
+    let mut line = String::new();
+
+    while !cancel.is_cancelled() {
+        let read = reader.read_line(&mut line).await?;
+        position += read as u64;
+        // A line without its newline is still being written; wait for the rest.
+        if line.ends_with('\n') {
+            match read_token_data(line.trim_end(), SchemaMode::Lenient) {
+                Ok(data) => on_record(data),
+                Err(err) => warn!("Skipping unreadable line: {}", err),
+            }
+            line.clear();
+            continue;
+        }
+        if read > 0 {
+            continue;
+        }
+
+        tokio::select! {
+            _ = cancel.cancelled() => break,
+            _ = sleep(TAIL_POLL_INTERVAL) => {}
+        }
+
+        let rotated = match tokio::fs::metadata(path).await {
+            Ok(metadata) => metadata.ino() != inode || metadata.len() < position,
+            // Between the old file going away and the new one appearing.
+            Err(_) => false,
+        };
+        if rotated {
+            info!("{} was rotated, reopening", path.display());
+            (reader, inode) = open(false).await?;
+            position = 0;
+            line.clear();
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::{sync::mpsc, time::timeout};
+
+    #[test]
+    fn diff_runs_reports_new_removed_and_changed_pools() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, b) = (dir.path().join("a.jsonl"), dir.path().join("b.jsonl"));
+        std::fs::write(
+            &a,
+            concat!(
+                "{\"lp_signature\":\"kept\",\"slot\":1}\n",
+                "{\"lp_signature\":\"gone\",\"slot\":2}\n",
+                "{\"lp_signature\":\"edited\",\"slot\":3}\n",
+            ),
+        )
+        .unwrap();
+        std::fs::write(
+            &b,
+            concat!(
+                "{\"lp_signature\":\"edited\",\"slot\":4}\n",
+                "{\"lp_signature\":\"kept\",\"slot\":1}\n",
+                "{\"lp_signature\":\"fresh\",\"slot\":5}\n",
+            ),
+        )
+        .unwrap();
+
+        let report = diff_runs(&a, &b).unwrap();
+
+        assert_eq!(
+            report,
+            DiffReport {
+                new: vec!["fresh".to_string()],
+                removed: vec!["gone".to_string()],
+                changed: vec!["edited".to_string()],
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn tail_emits_records_appended_after_it_starts_and_after_rotation() {
+        use std::io::Write;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pools.json");
+        let line = |signature: &str| {
+            format!(
+                "{{\"lp_signature\":\"{}\",\"timestamp\":\"\"}}\n",
+                signature
+            )
+        };
+        std::fs::write(&path, line("before")).unwrap();
+        let (sender, mut records) = mpsc::unbounded_channel();
+        let cancel = CancellationToken::new();
+        let tail = tokio::spawn({
+            let (path, cancel) = (path.clone(), cancel.clone());
+            async move {
+                tail_records(&path, &cancel, |data| {
+                    sender.send(data.lp_signature).unwrap()
+                })
+                .await
+            }
+        });
+
+        // Give the tailer time to open the file and seek to its end.
+        sleep(Duration::from_millis(100)).await;
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        file.write_all(line("appended").as_bytes()).unwrap();
+        let appended = timeout(Duration::from_secs(5), records.recv())
+            .await
+            .unwrap();
+        assert_eq!(appended.as_deref(), Some("appended"));
+
+        // Replaced by a new file, which is read from the start.
+        let rotated = dir.path().join("pools.json.new");
+        std::fs::write(&rotated, line("rotated")).unwrap();
+        std::fs::rename(&rotated, &path).unwrap();
+        let rotated = timeout(Duration::from_secs(5), records.recv())
+            .await
+            .unwrap();
+        assert_eq!(rotated.as_deref(), Some("rotated"));
+
+        cancel.cancel();
+        tail.await.unwrap().unwrap();
+    }
+
+    #[test]
+    fn repair_drops_the_truncated_last_line_and_keeps_the_rest() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pools.json");
+        let first = r#"{"lp_signature":"a","timestamp":""}"#;
+        let second = r#"{"lp_signature":"b","timestamp":""}"#;
+        let contents = format!(
+            "{}\nnot json\n\n{}\n{{\"lp_signature\":\"c\",\"ti",
+            first, second
+        );
+        std::fs::write(&path, contents).unwrap();
+
+        let report = repair_records(&path).unwrap();
+
+        assert_eq!(report.kept, 2);
+        assert_eq!(report.dropped, [2, 5]);
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            format!("{}\n{}\n", first, second)
+        );
+        // A clean file is left alone.
+        let report = repair_records(&path).unwrap();
+        assert_eq!((report.kept, report.dropped.len()), (2, 0));
+    }
+}
@@ -1,3 +1,4 @@
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use solana_client::rpc_client::RpcClient;
@@ -26,7 +27,9 @@ struct TokenData {
 struct TokenInfo {
     address: String,
     decimals: u8,
-    lp_amount: f64,
+    amount: u64,
+    #[serde(with = "rust_decimal::serde::str")]
+    lp_amount: Decimal,
 }
 
 #[derive(Error, Debug)]
@@ -37,12 +40,17 @@ enum StoreError {
     Serde(#[from] serde_json::Error),
 }
 
-use solana_transaction_status::{token_balances, UiTransactionEncoding, UiTransactionTokenBalance};
+use solana_transaction_status::{
+    option_serializer::OptionSerializer, token_balances, UiTransactionEncoding,
+    UiTransactionTokenBalance,
+};
 
 const LP_OWNER: &str = "5Q544fKrFoe6tsEbD7S8EmxGTJYAKtTVhAW5Q5pge4j1";
-const WSOL_MINT: &str = "So11111111111111111111111111111111111111112";
+/// CPMM's counterpart of `LP_OWNER`, the PDA owning its pool vaults.
+const CPMM_AUTHORITY: &str = "GpMZbSM2GgvTKHJirzeGfMFoaZ8UR2X7F4v8vHTvxFbL";
 
-async fn monitor_new_tokens(client: Arc<RpcClient>, ray_fee: Pubkey) {
+/// `authority` is whose post-token-balances are printed, the pool vaults' owner.
+async fn monitor_new_tokens(client: Arc<RpcClient>, ray_fee: Pubkey, authority: &str) {
     println!("Monitoring new Solana tokens...");
 
     loop {
@@ -70,35 +78,28 @@ async fn monitor_new_tokens(client: Arc<RpcClient>, ray_fee: Pubkey) {
                 encoding: Some(UiTransactionEncoding::Json),
             };
 
-            match client.get_transaction(&signature, UiTransactionEncoding::Json) {
+            match client.get_transaction_with_config(&signature, config) {
                 Ok(transaction) => {
                     let slot = transaction.slot;
-                    let signatures = transaction.transaction.transaction;
-                    let post_token_balances = transaction
-                        .transaction
-                        .meta
-                        .as_ref()
-                        .and_then(|meta| Some(meta.post_token_balances.clone()));
-
-                    let owner_address = "5Q544fKrFoe6tsEbD7S8EmxGTJYAKtTVhAW5Q5pge4j1";
-
-                    // let token_balances: Vec<UiTransactionTokenBalance> = match post_token_balances {
-                    //     Some(balances) => {
-                    //         // Extract the inner Vec from the OptionSerializer
-                    //         let inner_vec: Vec<UiTransactionTokenBalance> = balances.into();
-
-                    //         // Filter the balances based on the owner address
-                    //         inner_vec
-                    //             .into_iter()
-                    //             .filter(|balance| {
-                    //                 // Check if the owner matches the given address
-                    //                 balance.owner.as_ref()
-                    //                     == Some(&owner_address.to_string()).into()
-                    //             })
-                    //             .collect()
-                    //     }
-                    //     None => vec![], // Return an empty vector if no balances were found
-                    // };
+                    let signatures = &transaction.transaction.transaction;
+                    // Keep only the pool authority's balances, borrowed from the
+                    // response, so a transaction with thousands of balances isn't copied.
+                    let post_token_balances: Vec<&UiTransactionTokenBalance> =
+                        match transaction.transaction.meta.as_ref() {
+                            Some(meta) => match &meta.post_token_balances {
+                                OptionSerializer::Some(balances) => balances
+                                    .iter()
+                                    .filter(|balance| {
+                                        matches!(
+                                            &balance.owner,
+                                            OptionSerializer::Some(owner) if owner == authority
+                                        )
+                                    })
+                                    .collect(),
+                                OptionSerializer::None | OptionSerializer::Skip => Vec::new(),
+                            },
+                            None => Vec::new(),
+                        };
 
                     // Create JSON object
                     let transaction_json = json!({
@@ -108,7 +109,7 @@ async fn monitor_new_tokens(client: Arc<RpcClient>, ray_fee: Pubkey) {
                     });
 
                     // Print or handle the JSON object as needed
-                    println!("Transaction JSON: {}", transaction_json.to_string());
+                    println!("Transaction JSON: {}", transaction_json);
                 }
                 Err(e) => eprintln!("Error getting transaction: {}", e),
             }
@@ -118,38 +119,287 @@ async fn monitor_new_tokens(client: Arc<RpcClient>, ray_fee: Pubkey) {
     }
 }
 
-fn store_data<P: AsRef<Path>>(path: P, data: &TokenData) -> Result<(), StoreError> {
-    let file = OpenOptions::new().write(true).create(true).open(path)?;
+/// Append-only JSON-lines file that can be shared between tasks.
+///
+/// The `BufWriter` sits behind a mutex. Each record is written and flushed while
+/// the lock is held, so concurrent callers never interleave partial lines.
+#[derive(Clone)]
+struct JsonLinesStore {
+    writer: Arc<Mutex<BufWriter<File>>>,
+}
+
+impl JsonLinesStore {
+    fn open<P: AsRef<Path>>(path: P) -> Result<Self, StoreError> {
+        let file = OpenOptions::new().append(true).create(true).open(path)?;
+        Ok(Self {
+            writer: Arc::new(Mutex::new(BufWriter::new(file))),
+        })
+    }
+
+    fn store(&self, data: &TokenData) -> Result<(), StoreError> {
+        let json_data = serde_json::to_string(data)?;
 
-    let mut writer = BufWriter::new(file);
-    let json_data = serde_json::to_string(data)?;
+        // A panic mid-write can't leave a half line behind a flush, so keep going.
+        let mut writer = self
+            .writer
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
 
-    // Manage file size: Limit to a certain number of entries
-    if writer.get_ref().metadata()?.len() > 1_000_000 {
-        // 1 MB limit
-        // Optional: Handle file rotation or truncation logic here
-        println!("File size exceeded limit, consider implementing a rotation strategy.");
+        // Manage file size: Limit to a certain number of entries
+        if writer.get_ref().metadata()?.len() > 1_000_000 {
+            // 1 MB limit
+            // Optional: Handle file rotation or truncation logic here
+            println!("File size exceeded limit, consider implementing a rotation strategy.");
+        }
+
+        writeln!(writer, "{}", json_data)?;
+        writer.flush()?;
+        Ok(())
     }
+}
+
+/// Raydium AMM v4 program. Its logs are noisy; the fee account below is what
+/// pool creations reliably mention.
+const AMM_V4_PROGRAM: &str = "675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8";
+/// Account AMM v4 `initialize2` pays its pool-creation fee into.
+const AMM_V4_CREATE_FEE: &str = "7YttLkHDoNj9wyDur5pM1ejNaAvT9X4eqaYcHQqtj2G5";
+/// Raydium CPMM program, watched through its fee account the same way.
+const CPMM_PROGRAM: &str = "CPMMoo8L3F4NbTegBCKVNunggL7H1ZpdTHKxQB5qKP1C";
+/// Account CPMM `initialize` pays its `create_pool_fee` into.
+const CPMM_CREATE_FEE: &str = "DNXgeM9EiiaAbaWvwjHj9fQQLAX5ZsfHyvmYUNRAdNC8";
 
-    writeln!(writer, "{}", json_data)?;
-    Ok(())
+/// Which Raydium program's pool creations are watched, from `RAYDIUM_VARIANT`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum PoolVariant {
+    #[default]
+    AmmV4,
+    Cpmm,
+}
+
+impl PoolVariant {
+    const ALL: [PoolVariant; 2] = [PoolVariant::AmmV4, PoolVariant::Cpmm];
+
+    fn program_id(self) -> &'static str {
+        match self {
+            PoolVariant::AmmV4 => AMM_V4_PROGRAM,
+            PoolVariant::Cpmm => CPMM_PROGRAM,
+        }
+    }
+
+    /// The account every pool creation of this variant pays a fee into.
+    fn create_fee_account(self) -> &'static str {
+        match self {
+            PoolVariant::AmmV4 => AMM_V4_CREATE_FEE,
+            PoolVariant::Cpmm => CPMM_CREATE_FEE,
+        }
+    }
+
+    fn authority(self) -> &'static str {
+        match self {
+            PoolVariant::AmmV4 => LP_OWNER,
+            PoolVariant::Cpmm => CPMM_AUTHORITY,
+        }
+    }
+}
+
+impl FromStr for PoolVariant {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "amm_v4" | "ammv4" => Ok(PoolVariant::AmmV4),
+            "cpmm" => Ok(PoolVariant::Cpmm),
+            other => Err(format!(
+                "Unknown pool variant '{}', expected amm_v4 or cpmm",
+                other
+            )),
+        }
+    }
+}
+
+/// Picks the watched fee address from the CLI argument, then `env_value`
+/// (`RAYDIUM_RAY_FEE`), then `variant`'s fee account, and checks it isn't one
+/// of the pool programs itself.
+fn resolve_ray_fee(
+    arg: Option<&str>,
+    env_value: Option<String>,
+    variant: PoolVariant,
+) -> Result<Pubkey, String> {
+    let address = arg
+        .map(str::to_string)
+        .or(env_value)
+        .unwrap_or_else(|| variant.create_fee_account().to_string());
+
+    if let Some(program) = PoolVariant::ALL
+        .into_iter()
+        .find(|program| program.program_id() == address)
+    {
+        return Err(format!(
+            "{} is the {:?} program, not its fee account; watch {} instead",
+            address,
+            program,
+            program.create_fee_account()
+        ));
+    }
+    if address != variant.create_fee_account() {
+        println!(
+            "Watching {}, which is not the {:?} fee account {}",
+            address,
+            variant,
+            variant.create_fee_account()
+        );
+    }
+
+    Pubkey::from_str(&address).map_err(|e| format!("Invalid Ray Fee address {}: {}", address, e))
 }
 
 #[tokio::main]
 async fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() < 3 {
-        eprintln!("Usage: {} <RPC_URL> <RAY_FEE_ADDRESS>", args[0]);
+    if args.len() > 3 {
+        eprintln!("Usage: {} [RPC_URL] [RAY_FEE_ADDRESS]", args[0]);
         return;
     }
 
-    let rpc_url =
-        env::var("RPC_URL").unwrap_or_else(|_| "https://api.mainnet-beta.solana.com".to_string());
-
-    let raydium_address = "7YttLkHDoNj9wyDur5pM1ejNaAvT9X4eqaYcHQqtj2G5";
+    // CLI first, then the same env precedence as alternate_main3's AppConfig.
+    let rpc_url = args
+        .get(1)
+        .cloned()
+        .or_else(|| env::var("RAYDIUM_RPC_URL").ok())
+        .or_else(|| env::var("RPC_URL").ok())
+        .unwrap_or_else(|| "https://api.mainnet-beta.solana.com".to_string());
 
-    let ray_fee = Pubkey::from_str(&raydium_address).expect("Invalid Ray Fee address");
+    let variant = match env::var("RAYDIUM_VARIANT") {
+        Ok(variant) => match variant.parse::<PoolVariant>() {
+            Ok(variant) => variant,
+            Err(e) => {
+                eprintln!("{}", e);
+                return;
+            }
+        },
+        Err(_) => PoolVariant::default(),
+    };
+    let ray_fee = match resolve_ray_fee(
+        args.get(2).map(String::as_str),
+        env::var("RAYDIUM_RAY_FEE").ok(),
+        variant,
+    ) {
+        Ok(ray_fee) => ray_fee,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
     let client = Arc::new(RpcClient::new(rpc_url)); // Use configurable RPC endpoint
 
-    monitor_new_tokens(client, ray_fee).await;
+    monitor_new_tokens(client, ray_fee, variant.authority()).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+
+    fn token_data(n: usize) -> TokenData {
+        TokenData {
+            lp_signature: format!("sig{}", n),
+            creator: "creator".to_string(),
+            timestamp: "2024-06-01T00:00:00+00:00".to_string(),
+            tokens: vec![TokenInfo {
+                address: "So11111111111111111111111111111111111111112".to_string(),
+                decimals: 9,
+                amount: n as u64,
+                lp_amount: Decimal::from_i128_with_scale(n as i128, 9),
+            }],
+        }
+    }
+
+    #[test]
+    fn concurrent_stores_write_intact_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pools.jsonl");
+        let store = JsonLinesStore::open(&path).unwrap();
+
+        let writers: Vec<_> = (0..16)
+            .map(|writer| {
+                let store = store.clone();
+                std::thread::spawn(move || {
+                    for n in 0..50 {
+                        store.store(&token_data(writer * 50 + n)).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        let lines: Vec<String> = BufReader::new(File::open(&path).unwrap())
+            .lines()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(lines.len(), 16 * 50);
+        for line in lines {
+            serde_json::from_str::<TokenData>(&line).unwrap();
+        }
+    }
+
+    #[test]
+    fn the_cli_fee_address_beats_the_env_which_beats_the_default() {
+        let cli = Pubkey::new_unique().to_string();
+        let from_env = Pubkey::new_unique().to_string();
+
+        let resolve = |arg: Option<&str>, env_value: Option<&String>| {
+            resolve_ray_fee(arg, env_value.cloned(), PoolVariant::AmmV4)
+                .unwrap()
+                .to_string()
+        };
+
+        assert_eq!(resolve(Some(&cli), Some(&from_env)), cli);
+        assert_eq!(resolve(None, Some(&from_env)), from_env);
+        assert_eq!(resolve(None, None), AMM_V4_CREATE_FEE);
+    }
+
+    #[test]
+    fn each_variant_defaults_to_its_own_fee_account() {
+        for variant in PoolVariant::ALL {
+            let ray_fee = resolve_ray_fee(None, None, variant).unwrap();
+
+            assert_eq!(ray_fee.to_string(), variant.create_fee_account());
+        }
+        assert_eq!("cpmm".parse(), Ok(PoolVariant::Cpmm));
+        assert_eq!("AMM_V4".parse(), Ok(PoolVariant::AmmV4));
+        assert!("clmm".parse::<PoolVariant>().is_err());
+    }
+
+    #[test]
+    fn watching_a_pool_program_instead_of_its_fee_account_is_rejected() {
+        for variant in PoolVariant::ALL {
+            for program in PoolVariant::ALL {
+                let err = resolve_ray_fee(Some(program.program_id()), None, variant).unwrap_err();
+
+                assert_eq!(
+                    err,
+                    format!(
+                        "{} is the {:?} program, not its fee account; watch {} instead",
+                        program.program_id(),
+                        program,
+                        program.create_fee_account()
+                    )
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn a_malformed_fee_address_is_rejected() {
+        let err =
+            resolve_ray_fee(None, Some("not-a-pubkey".to_string()), PoolVariant::Cpmm).unwrap_err();
+
+        assert!(
+            err.starts_with("Invalid Ray Fee address not-a-pubkey"),
+            "{}",
+            err
+        );
+    }
 }